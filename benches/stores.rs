@@ -0,0 +1,95 @@
+//! Criterion benchmarks for each backend's write, read and clear paths, over the stores
+//! `tests/common` opens.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use performance_metrics_diff_dbs::db::{self, RunePoolStore, COMPILED_BACKENDS};
+use performance_metrics_diff_dbs::generator::{generate, DEFAULT_SEED};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const SIZES: [usize; 4] = [10, 400, 10_000, 100_000];
+
+/// Opens `backend`, or `None` if it is not configured or fails to open.
+fn open_store(rt: &Runtime, backend: &str) -> Option<(Arc<dyn RunePoolStore>, Option<TempDir>)> {
+    let (config, dir) = common::store_config(backend, |_| {})?;
+    match rt.block_on(db::connect(backend, &config)) {
+        Ok(store) => Some((store, dir)),
+        Err(e) => {
+            eprintln!("Skipping {}: {}", backend, e);
+            None
+        }
+    }
+}
+
+fn bench_backend(c: &mut Criterion, rt: &Runtime, backend: &str) {
+    let Some((store, _dir)) = open_store(rt, backend) else {
+        return;
+    };
+
+    let mut group = c.benchmark_group(format!("{}/write", backend));
+    group.sample_size(10);
+    for size in SIZES {
+        let data = generate(size, DEFAULT_SEED);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.to_async(rt).iter(|| async {
+                store.update_rune_pool(data).await.expect("write");
+            });
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/read", backend));
+    group.sample_size(10);
+    for size in SIZES {
+        let data = generate(size, DEFAULT_SEED);
+        rt.block_on(store.clear()).expect("clear");
+        rt.block_on(store.update_rune_pool(&data)).expect("write");
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.to_async(rt).iter(|| async {
+                store.get_rune_pool().await.expect("read");
+            });
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/clear", backend));
+    group.sample_size(10);
+    for size in SIZES {
+        let data = generate(size, DEFAULT_SEED);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            // Only the clear is timed; repopulating between iterations is not
+            b.to_async(rt).iter_custom(|iters| {
+                let store = store.clone();
+                async move {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        store.update_rune_pool(data).await.expect("write");
+                        let start = Instant::now();
+                        store.clear().await.expect("clear");
+                        total += start.elapsed();
+                    }
+                    total
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn stores(c: &mut Criterion) {
+    let rt = Runtime::new().expect("tokio runtime");
+    for backend in COMPILED_BACKENDS {
+        bench_backend(c, &rt, backend);
+    }
+}
+
+criterion_group!(benches, stores);
+criterion_main!(benches);
@@ -1,201 +1,1073 @@
+use crate::bench;
 use crate::config::Config;
-use crate::db::leveldb::LevelDBClient;
-use crate::db::mongodb::MongoDBClient;
-use crate::db::psql::PsqlClient;
-use crate::db::rocksdb::RocksDBClient;
-use crate::db::surrealdb::SurrealDBClient;
-use crate::models::rune_pool::{ApiRunePoolResponse, DbRunePoolResponse};
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use crate::db::registry::{open_backends, BackendSlot, BackendStatus};
+use crate::db::{Capabilities, IntervalRange, RunePoolStore, StoreError, WriteSemantics};
+use crate::generator::{self, DEFAULT_SEED};
+use crate::ingest::{self, IngestControl, TickReport};
+use crate::midgard;
+use crate::models::rune_pool::{ApiMeta, ApiRunePoolResponse, DbRunePoolResponse};
+use crate::models::timing::{encoded_len, Op, Timing, TimingReport};
+use crate::results::{NewRun, ResultsStore};
+use crate::verify::{self, LastWrite};
+use axum::{extract::Path, extract::Query, extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use rand::seq::SliceRandom;
 use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
 use std::error::Error;
-use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::Mutex;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
 
 #[derive(Clone)]
 pub struct AppState {
     config: Config,
-    leveldb: Arc<LevelDBClient>,
-    rocksdb: Arc<RocksDBClient>,
-    surrealdb: Arc<Mutex<SurrealDBClient>>,
-    psql: Arc<Mutex<PsqlClient>>,
-    mongodb: Arc<Mutex<MongoDBClient>>,
+    backends: Vec<Arc<BackendSlot>>,
+    results: Arc<ResultsStore>,
+    /// What `/verify` checks the stores against; reset by `/clear` and `/bench`.
+    last_write: Arc<RwLock<Option<LastWrite>>>,
     http_client: HttpClient,
+    ingest: Arc<IngestControl>,
 }
 
 impl AppState {
     pub async fn new(config: Config) -> Result<Self, Box<dyn Error>> {
-        let leveldb = Arc::new(LevelDBClient::new(&config)?);
-        let rocksdb = Arc::new(RocksDBClient::new(&config)?);
-        let surrealdb = Arc::new(Mutex::new(SurrealDBClient::new(&config).await?));
-        let psql = Arc::new(Mutex::new(PsqlClient::new(&config).await?));
-        let mongodb = Arc::new(Mutex::new(MongoDBClient::new(&config).await?));
+        let backends = open_backends(&config).await?;
+        let results = Arc::new(ResultsStore::open(&config).await?);
         let http_client = HttpClient::new();
+        let ingest = Arc::new(IngestControl::new(config.ingest_every.map(Duration::from_secs)));
 
         Ok(AppState {
             config,
-            leveldb,
-            rocksdb,
-            surrealdb,
-            psql,
-            mongodb,
+            backends,
+            results,
+            last_write: Arc::new(RwLock::new(None)),
             http_client,
+            ingest,
         })
     }
+
+    fn backend(&self, name: &str) -> Option<&Arc<BackendSlot>> {
+        self.backends.iter().find(|backend| backend.name() == name)
+    }
+
+    /// Stores that are currently connected; unavailable backends are skipped.
+    fn available_stores(&self) -> Vec<Arc<dyn RunePoolStore>> {
+        self.backends.iter().filter_map(|backend| backend.store()).collect()
+    }
+
+    /// The store `/fetch-and-update` continues from: `requested`, else `SYNC_SOURCE`, else
+    /// the first available store.
+    fn sync_source(&self, requested: Option<&str>) -> Result<Arc<dyn RunePoolStore>, (StatusCode, String)> {
+        let Some(name) = requested.or(self.config.sync_source.as_deref()) else {
+            return self.available_stores().into_iter().next().ok_or((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "No database is available to sync from".to_string(),
+            ));
+        };
+        let backend = self
+            .backend(name)
+            .ok_or((StatusCode::BAD_REQUEST, format!("Unknown database: {}", name)))?;
+        backend.store().ok_or((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Database {} is unavailable", name),
+        ))
+    }
+
+    fn set_last_write(&self, last_write: Option<LastWrite>) {
+        *self.last_write.write().unwrap() = last_write;
+    }
+
+    fn backend_statuses(&self) -> HashMap<String, BackendStatus> {
+        self.backends
+            .iter()
+            .map(|backend| (backend.name().to_string(), backend.status()))
+            .collect()
+    }
+
+    /// Appends `run` to the results history; a failure to record never fails the request.
+    async fn record_run(&self, run: NewRun) -> Option<u64> {
+        match self.results.record(run).await {
+            Ok(id) => Some(id),
+            Err(e) => {
+                eprintln!("Failed to record run: {}", e);
+                None
+            }
+        }
+    }
 }
 
-pub async fn update_rune_pool(
-    State(state): State<AppState>,
-    Json(payload): Json<ApiRunePoolResponse>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let db_response: DbRunePoolResponse = payload.clone().into();
-    let mut timings = HashMap::new();
+fn internal_error(err: StoreError) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
 
-    let start = Instant::now();
-    state.leveldb.update_rune_pool(&db_response).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("leveldb", start.elapsed().as_millis());
+/// Turns per-backend failures into a single 500, or `Ok` if every backend succeeded.
+fn check_errors(errors: &HashMap<String, String>) -> Result<(), (StatusCode, String)> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut messages: Vec<_> = errors
+        .iter()
+        .map(|(backend, error)| format!("{}: {}", backend, error))
+        .collect();
+    messages.sort();
+    Err((StatusCode::INTERNAL_SERVER_ERROR, messages.join("; ")))
+}
 
-    let start = Instant::now();
-    state.rocksdb.update_rune_pool(&db_response).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("rocksdb", start.elapsed().as_millis());
+/// Unit of the per-backend values in each response's `timings` map.
+const TIMING_UNIT: &str = "us";
 
-    let start = Instant::now();
-    state.surrealdb.lock().await.update_rune_pool(&db_response).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("surrealdb", start.elapsed().as_millis());
+fn timing_map(timings: &[Timing]) -> HashMap<&str, u64> {
+    timings
+        .iter()
+        .map(|timing| (timing.backend.as_str(), timing.micros()))
+        .collect()
+}
 
-    let start = Instant::now();
-    state.psql.lock().await.update_rune_pool(&db_response).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("psql", start.elapsed().as_millis());
+fn timing_details(timings: &[Timing]) -> Vec<TimingReport> {
+    timings.iter().map(Timing::report).collect()
+}
 
-    let start = Instant::now();
-    state.mongodb.lock().await.update_rune_pool(&db_response).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("mongodb", start.elapsed().as_millis());
+/// How `/update` and `/fetch-and-update` schedule writes across backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteMode {
+    /// One backend after another, in registration order.
+    #[default]
+    Sequential,
+    /// All backends at once; embedded stores run on blocking threads.
+    Parallel,
+    /// One backend after another, in a random order per request.
+    Shuffled,
+}
+
+impl WriteMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteMode::Sequential => "sequential",
+            WriteMode::Parallel => "parallel",
+            WriteMode::Shuffled => "shuffled",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WriteParams {
+    #[serde(default)]
+    mode: WriteMode,
+    #[serde(default)]
+    semantics: WriteSemantics,
+    /// Per-backend write strategies as `backend:strategy` pairs, e.g. `psql:copy`.
+    strategy: Option<String>,
+}
+
+/// Everything a write request chose about how the data is written.
+struct WritePlan {
+    mode: WriteMode,
+    semantics: WriteSemantics,
+    /// Strategy per backend name; backends not listed use their default.
+    strategies: HashMap<String, String>,
+}
+
+impl WritePlan {
+    /// Builds the plan, rejecting strategies that a connected backend does not support.
+    fn new(
+        state: &AppState,
+        mode: WriteMode,
+        semantics: WriteSemantics,
+        strategy: Option<&str>,
+    ) -> Result<Self, (StatusCode, String)> {
+        let mut strategies = HashMap::new();
+        for pair in strategy.unwrap_or_default().split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (backend, name) = pair.split_once(':').ok_or((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid strategy '{}', expected backend:strategy", pair),
+            ))?;
+            let (backend, name) = (backend.trim().to_lowercase(), name.trim().to_lowercase());
+            let slot = state
+                .backend(&backend)
+                .ok_or((StatusCode::BAD_REQUEST, format!("Unknown database: {}", backend)))?;
+            if let Some(store) = slot.store() {
+                if !store.write_strategies().contains(&name.as_str()) {
+                    let error = StoreError::UnknownStrategy { backend, strategy: name };
+                    return Err((StatusCode::BAD_REQUEST, error.to_string()));
+                }
+            }
+            strategies.insert(backend, name);
+        }
+        Ok(WritePlan {
+            mode,
+            semantics,
+            strategies,
+        })
+    }
+
+    /// The strategy `store` is written with: the requested one or its default.
+    fn strategy_for(&self, store: &dyn RunePoolStore) -> Option<String> {
+        self.strategies
+            .get(store.name())
+            .cloned()
+            .or_else(|| store.default_write_strategy().map(str::to_string))
+    }
+
+    /// Request options recorded in the run history.
+    fn params(&self) -> HashMap<String, String> {
+        let mut params = HashMap::from([
+            ("mode".to_string(), self.mode.as_str().to_string()),
+            ("semantics".to_string(), self.semantics.as_str().to_string()),
+        ]);
+        let mut strategies: Vec<_> = self
+            .strategies
+            .iter()
+            .map(|(backend, strategy)| format!("{}:{}", backend, strategy))
+            .collect();
+        if !strategies.is_empty() {
+            strategies.sort();
+            params.insert("strategy".to_string(), strategies.join(","));
+        }
+        params
+    }
+}
+
+/// Timings per backend plus the order the writes were started in.
+#[derive(Default)]
+struct WriteOutcome {
+    timings: Vec<Timing>,
+    errors: HashMap<String, String>,
+    order: Vec<&'static str>,
+    rows: usize,
+    bytes: usize,
+}
+
+impl WriteOutcome {
+    fn to_run(&self, endpoint: &'static str, params: HashMap<String, String>) -> NewRun {
+        NewRun {
+            endpoint,
+            params,
+            rows: self.rows as u64,
+            bytes: self.bytes as u64,
+            timings: self.timings.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+/// Writes `db_response` to every available store according to `plan`, timing each one.
+///
+/// A failing backend does not stop the others; its error is collected in the outcome.
+async fn write_all(
+    state: &AppState,
+    db_response: Arc<DbRunePoolResponse>,
+    plan: &WritePlan,
+) -> WriteOutcome {
+    let mut stores = state.available_stores();
+    if plan.mode == WriteMode::Shuffled {
+        stores.shuffle(&mut rand::rng());
+    }
+    let order = stores.iter().map(|store| store.name()).collect();
+    let rows = db_response.intervals.len();
+    let bytes = encoded_len(&*db_response);
+    state.set_last_write(Some(LastWrite {
+        payload: db_response.clone(),
+        semantics: plan.semantics,
+    }));
+
+    let mut timings = Vec::new();
+    let mut errors = HashMap::new();
+    match plan.mode {
+        WriteMode::Sequential | WriteMode::Shuffled => {
+            for store in stores {
+                let requested = plan.strategies.get(store.name()).map(String::as_str);
+                let start = Instant::now();
+                match store.write_rune_pool(&db_response, plan.semantics, requested).await {
+                    Ok(()) => {
+                        let timing = Timing::new(store.name(), Op::Write, start.elapsed(), rows, bytes)
+                            .with_strategy(plan.strategy_for(store.as_ref()).as_deref())
+                            .with_settings(store.settings(Op::Write))
+                            .with_encoded_bytes(store.encoded_len(&db_response));
+                        timings.push(timing);
+                    }
+                    Err(e) => {
+                        errors.insert(store.name().to_string(), e.to_string());
+                    }
+                }
+            }
+        }
+        WriteMode::Parallel => {
+            let handles: Vec<_> = stores
+                .into_iter()
+                .map(|store| {
+                    let strategy = plan.strategy_for(store.as_ref());
+                    let settings = store.settings(Op::Write);
+                    let encoded_bytes = store.encoded_len(&db_response);
+                    let requested = plan.strategies.get(store.name()).cloned();
+                    let handle = spawn_timed_write(store.clone(), db_response.clone(), plan.semantics, requested);
+                    (store.name(), strategy, settings, encoded_bytes, handle)
+                })
+                .collect();
+            for (name, strategy, settings, encoded_bytes, handle) in handles {
+                match handle.await {
+                    Ok(Ok(elapsed)) => {
+                        let timing = Timing::new(name, Op::Write, elapsed, rows, bytes)
+                            .with_strategy(strategy.as_deref())
+                            .with_settings(settings)
+                            .with_encoded_bytes(encoded_bytes);
+                        timings.push(timing);
+                    }
+                    Ok(Err(e)) => {
+                        errors.insert(name.to_string(), e.to_string());
+                    }
+                    Err(e) => {
+                        errors.insert(name.to_string(), format!("write task failed: {}", e));
+                    }
+                }
+            }
+        }
+    }
+    WriteOutcome {
+        timings,
+        errors,
+        order,
+        rows,
+        bytes,
+    }
+}
+
+fn spawn_timed_write(
+    store: Arc<dyn RunePoolStore>,
+    db_response: Arc<DbRunePoolResponse>,
+    semantics: WriteSemantics,
+    strategy: Option<String>,
+) -> JoinHandle<Result<Duration, StoreError>> {
+    let embedded = store.capabilities().embedded;
+    let write = async move {
+        let start = Instant::now();
+        store.write_rune_pool(&db_response, semantics, strategy.as_deref()).await?;
+        Ok(start.elapsed())
+    };
+    if embedded {
+        // Embedded stores do their I/O on the calling thread, so keep them off the async workers
+        tokio::task::spawn_blocking(move || Handle::current().block_on(write))
+    } else {
+        tokio::spawn(write)
+    }
+}
+
+pub async fn update_rune_pool(
+    State(state): State<AppState>,
+    Query(params): Query<WriteParams>,
+    Json(payload): Json<ApiRunePoolResponse>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let plan = WritePlan::new(&state, params.mode, params.semantics, params.strategy.as_deref())?;
+    let db_response: DbRunePoolResponse = payload.clone().into();
+    let backends = state.backend_statuses();
+    let outcome = write_all(&state, Arc::new(db_response), &plan).await;
+    let run_id = state.record_run(outcome.to_run("update", plan.params())).await;
+    check_errors(&outcome.errors)?;
 
     Ok((StatusCode::OK, Json(json!({
         "data": payload,
-        "timings": timings
+        "run_id": run_id,
+        "unit": TIMING_UNIT,
+        "timings": timing_map(&outcome.timings),
+        "details": timing_details(&outcome.timings),
+        "mode": params.mode,
+        "semantics": params.semantics,
+        "order": outcome.order,
+        "backends": backends
     }))))
 }
 
+/// `/get` query: the backend plus an optional `start_time` range and page.
+#[derive(Deserialize)]
+pub struct GetParams {
+    db: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+impl GetParams {
+    fn range(&self) -> IntervalRange {
+        IntervalRange {
+            from: self.from,
+            to: self.to,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
 pub async fn get_rune_pool(
     State(state): State<AppState>,
-    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+    Query(params): Query<GetParams>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let db = params.get("db").ok_or((
+    let db = params.db.as_ref().ok_or((
         StatusCode::BAD_REQUEST,
         "Missing 'db' query parameter".to_string(),
     ))?;
+    let range = params.range();
+    if let (Some(from), Some(to)) = (range.from, range.to) {
+        if from >= to {
+            return Err((StatusCode::BAD_REQUEST, "'from' must be less than 'to'".to_string()));
+        }
+    }
+    let backend = state
+        .backend(db)
+        .ok_or((StatusCode::BAD_REQUEST, format!("Unknown database: {}", db)))?;
+    let store = backend.store().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("Database {} is unavailable", db),
+    ))?;
 
-    let (retrieved_api, timing): (ApiRunePoolResponse, u128) = match db.as_str() {
-        "leveldb" => {
-            let start = Instant::now();
-            let retrieved_db = state.leveldb.get_rune_pool().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            (retrieved_db.into(), start.elapsed().as_millis())
-        }
-        "rocksdb" => {
-            let start = Instant::now();
-            let retrieved_db = state.rocksdb.get_rune_pool().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            (retrieved_db.into(), start.elapsed().as_millis())
-        }
-        "surrealdb" => {
-            let start = Instant::now();
-            let retrieved_db = state.surrealdb.lock().await.get_rune_pool().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            (retrieved_db.into(), start.elapsed().as_millis())
-        }
-        "psql" => {
-            let start = Instant::now();
-            let retrieved_db = state.psql.lock().await.get_rune_pool().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            (retrieved_db.into(), start.elapsed().as_millis())
-        }
-        "mongodb" => {
-            let start = Instant::now();
-            let retrieved_db = state.mongodb.lock().await.get_rune_pool().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            (retrieved_db.into(), start.elapsed().as_millis())
-        }
-        _ => return Err((StatusCode::BAD_REQUEST, format!("Unknown database: {}", db))),
+    // Without a range the full read path is measured, as before
+    let start = Instant::now();
+    let result = if range.is_unbounded() {
+        store.get_rune_pool().await
+    } else {
+        store.get_range(&range).await
     };
+    let elapsed = start.elapsed();
+    let retrieved_db = match result {
+        Ok(retrieved_db) => retrieved_db,
+        Err(e) => {
+            let errors = HashMap::from([(store.name().to_string(), e.to_string())]);
+            state
+                .record_run(NewRun { endpoint: "get", params: range_params(&range), errors, ..Default::default() })
+                .await;
+            return Err(internal_error(e));
+        }
+    };
+    let timing = Timing::new(
+        store.name(),
+        Op::Read,
+        elapsed,
+        retrieved_db.intervals.len(),
+        encoded_len(&retrieved_db),
+    )
+    .with_settings(store.settings(Op::Read))
+    .with_encoded_bytes(store.encoded_len(&retrieved_db));
+    let run_id = state
+        .record_run(NewRun {
+            endpoint: "get",
+            params: range_params(&range),
+            rows: timing.rows,
+            bytes: timing.bytes,
+            timings: vec![timing.clone()],
+            ..Default::default()
+        })
+        .await;
+    let retrieved_api: ApiRunePoolResponse = retrieved_db.into();
 
     Ok((StatusCode::OK, Json(json!({
         "data": retrieved_api,
-        "timing": timing
+        "run_id": run_id,
+        "unit": TIMING_UNIT,
+        "timing": timing.micros(),
+        "details": timing.report(),
+        "range": range
     }))))
 }
 
+/// The set fields of `range`, for the run history.
+fn range_params(range: &IntervalRange) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for (key, value) in [("from", range.from), ("to", range.to), ("limit", range.limit.map(|limit| limit as u64))] {
+        if let Some(value) = value {
+            params.insert(key.to_string(), value.to_string());
+        }
+    }
+    if range.offset > 0 {
+        params.insert("offset".to_string(), range.offset.to_string());
+    }
+    params
+}
+
+/// `/fetch-and-update` query: how to write, plus where the fetch continues from.
+#[derive(Deserialize)]
+pub struct FetchParams {
+    #[serde(default)]
+    mode: WriteMode,
+    strategy: Option<String>,
+    /// Backend whose last stored interval the fetch continues from; defaults to `SYNC_SOURCE`.
+    source: Option<String>,
+    /// Refetch the initial window and replace the stored data instead of appending.
+    #[serde(default)]
+    full: bool,
+}
+
+/// One incremental sync: what was fetched, and how writing it went.
+struct SyncOutcome {
+    source: &'static str,
+    since: u64,
+    semantics: WriteSemantics,
+    appended: usize,
+    data: DbRunePoolResponse,
+    outcome: WriteOutcome,
+    run_id: Option<u64>,
+}
+
+/// Upserts the intervals Midgard has from the start of the source backend's last stored
+/// interval on to every backend, refreshing that interval since it was still open when
+/// fetched, and records the run under `endpoint`. An empty source, or `full`, loads the
+/// window from `initial_from` instead. Backend write errors are left in the outcome.
+async fn sync(
+    state: &AppState,
+    endpoint: &'static str,
+    source: Option<&str>,
+    full: bool,
+    mode: WriteMode,
+    strategy: Option<&str>,
+) -> Result<SyncOutcome, (StatusCode, String)> {
+    let source = state.sync_source(source)?;
+    let stored = if full {
+        None
+    } else {
+        match source.get_meta().await {
+            Ok(meta) => Some(meta),
+            Err(StoreError::MetaNotFound) => None,
+            Err(e) => return Err(internal_error(e)),
+        }
+    };
+    let (semantics, since) = match &stored {
+        Some(meta) => {
+            let last = source.get_last_interval().await.map_err(internal_error)?;
+            let since = last.map_or(meta.end_time, |last| last.start_time);
+            (WriteSemantics::Upsert, since)
+        }
+        None => (WriteSemantics::Replace, state.config.initial_from),
+    };
+    let plan = WritePlan::new(state, mode, semantics, strategy)?;
+
+    let page = midgard::fetch(&state.http_client, &state.config, since)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let data = match &stored {
+        Some(meta) => midgard::append(meta, since, page.into()),
+        None => page.into(),
+    };
+    let appended = data.intervals.len();
+
+    // Midgard has nothing from `since` on, so there is nothing to write or time
+    let outcome = if stored.is_some() && appended == 0 {
+        WriteOutcome::default()
+    } else {
+        write_all(state, Arc::new(data.clone()), &plan).await
+    };
+    let mut run_params = plan.params();
+    run_params.insert("source".to_string(), source.name().to_string());
+    run_params.insert("since".to_string(), since.to_string());
+    let run_id = state.record_run(outcome.to_run(endpoint, run_params)).await;
+
+    Ok(SyncOutcome {
+        source: source.name(),
+        since,
+        semantics,
+        appended,
+        data,
+        outcome,
+        run_id,
+    })
+}
+
+/// Runs one incremental sync; see `sync`.
 pub async fn fetch_and_update_rune_pool(
     State(state): State<AppState>,
+    Query(params): Query<FetchParams>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let url = format!(
-        "{}?interval={}&from={}&count=400",
-        state.config.api_url,
-        state.config.interval,
-        state.config.initial_from
-    );
-
-    let response = state
-        .http_client
-        .get(&url)
-        .send()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to fetch from Midgard: {}", e)))?
-        .json::<ApiRunePoolResponse>()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to parse Midgard response: {}", e)))?;
+    let backends = state.backend_statuses();
+    let sync = sync(
+        &state,
+        "fetch-and-update",
+        params.source.as_deref(),
+        params.full,
+        params.mode,
+        params.strategy.as_deref(),
+    )
+    .await?;
+    check_errors(&sync.outcome.errors)?;
+    let data: ApiRunePoolResponse = sync.data.into();
+
+    Ok((StatusCode::OK, Json(json!({
+        "data": data,
+        "run_id": sync.run_id,
+        "source": sync.source,
+        "since": sync.since,
+        "appended": sync.appended,
+        "unit": TIMING_UNIT,
+        "timings": timing_map(&sync.outcome.timings),
+        "details": timing_details(&sync.outcome.timings),
+        "mode": params.mode,
+        "semantics": sync.semantics,
+        "order": sync.outcome.order,
+        "backends": backends
+    }))))
+}
 
-    let db_response: DbRunePoolResponse = response.clone().into();
-    let mut timings = HashMap::new();
+/// Background ingestion, spawned from `main` when `INGEST_EVERY` is set: ticks right away,
+/// then every `INGEST_EVERY` seconds on a grid that starts at the current `INTERVAL`
+/// period, appending what Midgard has published since the last tick. Each tick is
+/// recorded as an `ingest` run; ticks while paused are skipped.
+pub async fn run_ingest(state: AppState) {
+    let Some(every) = state.ingest.every() else {
+        return;
+    };
+    if ingest::period_start(&state.config.interval, Utc::now()).is_none() {
+        eprintln!("Ingestion disabled: unknown INTERVAL `{}`", state.config.interval);
+        return;
+    }
 
-    let start = Instant::now();
-    state.leveldb.update_rune_pool(&db_response).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("leveldb", start.elapsed().as_millis());
+    loop {
+        if !state.ingest.is_paused() {
+            let report = ingest_tick(&state).await;
+            if let Some(error) = &report.error {
+                eprintln!("Ingest tick failed: {}", error);
+            }
+            state.ingest.record(report);
+        }
 
-    let start = Instant::now();
-    state.rocksdb.update_rune_pool(&db_response).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("rocksdb", start.elapsed().as_millis());
+        let now = Utc::now();
+        let anchor = ingest::period_start(&state.config.interval, now).expect("interval checked above");
+        let next = ingest::next_tick(anchor, every, now);
+        tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+    }
+}
 
-    let start = Instant::now();
-    state.surrealdb.lock().await.update_rune_pool(&db_response).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("surrealdb", start.elapsed().as_millis());
+async fn ingest_tick(state: &AppState) -> TickReport {
+    let at = Utc::now().to_rfc3339();
+    match sync(state, "ingest", None, false, WriteMode::Sequential, None).await {
+        Ok(sync) => TickReport {
+            at,
+            since: Some(sync.since),
+            appended: sync.appended,
+            run_id: sync.run_id,
+            timings: sync
+                .outcome
+                .timings
+                .iter()
+                .map(|timing| (timing.backend.clone(), timing.micros()))
+                .collect(),
+            error: check_errors(&sync.outcome.errors).err().map(|(_, message)| message),
+        },
+        // Nothing was written, but the failed tick still goes into the run history
+        Err((_, message)) => {
+            let errors = HashMap::from([("ingest".to_string(), message.clone())]);
+            let run_id = state
+                .record_run(NewRun { endpoint: "ingest", errors, ..Default::default() })
+                .await;
+            TickReport {
+                at,
+                since: None,
+                appended: 0,
+                run_id,
+                timings: HashMap::new(),
+                error: Some(message),
+            }
+        }
+    }
+}
 
-    let start = Instant::now();
-    state.psql.lock().await.update_rune_pool(&db_response).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("psql", start.elapsed().as_millis());
+/// Pauses or resumes the ingestion loop; `409` if it is not running.
+fn set_ingest_paused(state: &AppState, paused: bool) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if state.ingest.every().is_none() {
+        return Err((
+            StatusCode::CONFLICT,
+            "Ingestion is not enabled; set INGEST_EVERY".to_string(),
+        ));
+    }
+    state.ingest.set_paused(paused);
+    Ok(Json(state.ingest.status()))
+}
 
-    let start = Instant::now();
-    state.mongodb.lock().await.update_rune_pool(&db_response).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("mongodb", start.elapsed().as_millis());
+pub async fn pause_ingest(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    set_ingest_paused(&state, true)
+}
+
+pub async fn resume_ingest(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    set_ingest_paused(&state, false)
+}
+
+/// `/backfill` query: the `start_time` range to load and how to write it.
+#[derive(Deserialize)]
+pub struct BackfillParams {
+    from: u64,
+    /// Exclusive end of the range; defaults to now.
+    to: Option<u64>,
+    #[serde(default)]
+    mode: WriteMode,
+    #[serde(default)]
+    semantics: WriteSemantics,
+    strategy: Option<String>,
+    /// Return the merged intervals, not just their meta.
+    #[serde(default)]
+    echo: bool,
+}
+
+/// Loads `from..to` from Midgard one 400-interval page at a time and writes each page to
+/// every backend as it arrives. The first page is written with `semantics` and later ones
+/// are upserted, each under the meta of everything loaded so far, so the stores hold a
+/// consistent dataset after every page. Each page is recorded as its own run. Under the
+/// `blob` and `chunked` layouts every upsert rewrites the whole stored dataset, so a
+/// backfill of `n` pages writes O(n²) intervals.
+pub async fn backfill_rune_pool(
+    State(state): State<AppState>,
+    Query(params): Query<BackfillParams>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let to = params.to.unwrap_or_else(|| Utc::now().timestamp() as u64);
+    if params.from >= to {
+        return Err((StatusCode::BAD_REQUEST, "'from' must be less than 'to'".to_string()));
+    }
+    let first_plan = WritePlan::new(&state, params.mode, params.semantics, params.strategy.as_deref())?;
+    let next_plan = WritePlan::new(&state, params.mode, WriteSemantics::Upsert, params.strategy.as_deref())?;
+    let backends = state.backend_statuses();
 
+    let mut pages = midgard::Pages::new(&state.http_client, &state.config, params.from, to);
+    let mut merged = midgard::Merged::new(params.echo);
+    let mut run_ids = Vec::new();
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    let mut page_count = 0;
+    loop {
+        // Where this page starts, so a failed backfill can be resumed from it
+        let window_from = pages.cursor();
+        let resume = |message: String| format!("{}; resume with from={}", message, window_from);
+        let page = match pages.next().await {
+            Ok(Some(page)) => page,
+            Ok(None) => break,
+            Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, resume(e.to_string()))),
+        };
+        merged.add(&page);
+        let meta = merged.meta().expect("merged pages have intervals");
+        let db_response: DbRunePoolResponse = ApiRunePoolResponse { meta, intervals: page }.into();
+
+        let plan = if page_count == 0 { &first_plan } else { &next_plan };
+        let outcome = write_all(&state, Arc::new(db_response), plan).await;
+        page_count += 1;
+        let mut run_params = plan.params();
+        run_params.insert("page".to_string(), page_count.to_string());
+        run_params.insert("from".to_string(), window_from.to_string());
+        run_ids.push(state.record_run(outcome.to_run("backfill", run_params)).await);
+        for timing in &outcome.timings {
+            *totals.entry(timing.backend.clone()).or_default() += timing.micros();
+        }
+        check_errors(&outcome.errors).map_err(|(status, message)| (status, resume(message)))?;
+    }
+
+    let intervals = merged.len();
+    let meta = merged.meta();
+    let data = merged.into_response();
+    Ok((StatusCode::OK, Json(json!({
+        "meta": meta,
+        "data": data,
+        "from": params.from,
+        "to": to,
+        "pages": page_count,
+        "intervals": intervals,
+        "run_ids": run_ids,
+        "unit": TIMING_UNIT,
+        "timings": totals,
+        "mode": params.mode,
+        "semantics": params.semantics,
+        "backends": backends
+    }))))
+}
+
+#[derive(Deserialize)]
+pub struct GenerateParams {
+    intervals: usize,
+    #[serde(default = "default_seed")]
+    seed: u64,
+    #[serde(default)]
+    mode: WriteMode,
+    #[serde(default)]
+    semantics: WriteSemantics,
+    strategy: Option<String>,
+}
+
+pub async fn generate_and_update_rune_pool(
+    State(state): State<AppState>,
+    Query(params): Query<GenerateParams>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let plan = WritePlan::new(&state, params.mode, params.semantics, params.strategy.as_deref())?;
+    let db_response = generator::generate(params.intervals, params.seed);
+    let meta = ApiMeta::from(db_response.meta.clone());
+    let backends = state.backend_statuses();
+    let outcome = write_all(&state, Arc::new(db_response), &plan).await;
+    let mut run_params = plan.params();
+    run_params.insert("seed".to_string(), params.seed.to_string());
+    let run_id = state.record_run(outcome.to_run("generate-and-update", run_params)).await;
+    check_errors(&outcome.errors)?;
+
+    // The generated intervals can run to millions, so only the meta is echoed back
     Ok((StatusCode::OK, Json(json!({
-        "data": response,
-        "timings": timings
+        "meta": meta,
+        "run_id": run_id,
+        "intervals": params.intervals,
+        "seed": params.seed,
+        "unit": TIMING_UNIT,
+        "timings": timing_map(&outcome.timings),
+        "details": timing_details(&outcome.timings),
+        "mode": params.mode,
+        "semantics": params.semantics,
+        "order": outcome.order,
+        "backends": backends
     }))))
 }
 
 pub async fn clear_databases(
     State(state): State<AppState>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let mut timings = HashMap::new();
+    let mut timings = Vec::new();
+    let mut errors = HashMap::new();
+    let backends = state.backend_statuses();
 
-    let start = Instant::now();
-    state.leveldb.clear().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("leveldb", start.elapsed().as_millis());
+    for store in state.available_stores() {
+        let start = Instant::now();
+        match store.clear().await {
+            Ok(()) => timings.push(Timing::new(store.name(), Op::Clear, start.elapsed(), 0, 0)),
+            Err(e) => {
+                errors.insert(store.name().to_string(), e.to_string());
+            }
+        }
+    }
 
-    let start = Instant::now();
-    state.rocksdb.clear().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("rocksdb", start.elapsed().as_millis());
+    state.set_last_write(None);
 
-    let start = Instant::now();
-    state.surrealdb.lock().await.clear().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("surrealdb", start.elapsed().as_millis());
+    let run_id = state
+        .record_run(NewRun {
+            endpoint: "clear",
+            timings: timings.clone(),
+            errors: errors.clone(),
+            ..Default::default()
+        })
+        .await;
+    check_errors(&errors)?;
 
-    let start = Instant::now();
-    state.psql.lock().await.clear().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("psql", start.elapsed().as_millis());
+    Ok((StatusCode::OK, Json(json!({
+        "run_id": run_id,
+        "unit": TIMING_UNIT,
+        "timings": timing_map(&timings),
+        "details": timing_details(&timings),
+        "backends": backends
+    })))) // Changed to 200 OK with timings
+}
 
-    let start = Instant::now();
-    state.mongodb.lock().await.clear().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    timings.insert("mongodb", start.elapsed().as_millis());
+fn default_bench_iterations() -> u32 {
+    20
+}
+
+fn default_seed() -> u64 {
+    DEFAULT_SEED
+}
+
+/// Body of `POST /bench`: either a concrete `payload` or a synthetic `intervals` count.
+#[derive(Deserialize)]
+pub struct BenchRequest {
+    payload: Option<ApiRunePoolResponse>,
+    intervals: Option<usize>,
+    #[serde(default = "default_seed")]
+    seed: u64,
+    #[serde(default = "default_bench_iterations")]
+    iterations: u32,
+    #[serde(default)]
+    warmup: u32,
+}
+
+pub async fn run_benchmark(
+    State(state): State<AppState>,
+    Json(request): Json<BenchRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let data: DbRunePoolResponse = match (request.payload, request.intervals) {
+        (Some(payload), None) => payload.into(),
+        (None, Some(intervals)) => generator::generate(intervals, request.seed),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Provide exactly one of 'payload' or 'intervals'".to_string(),
+            ))
+        }
+    };
+    if request.iterations == 0 {
+        return Err((StatusCode::BAD_REQUEST, "'iterations' must be at least 1".to_string()));
+    }
+    if request.warmup.checked_add(request.iterations).is_none_or(|cycles| cycles > bench::MAX_CYCLES) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("'warmup' plus 'iterations' must be at most {}", bench::MAX_CYCLES),
+        ));
+    }
+
+    let backends = state.backend_statuses();
+    let results = bench::run(&state.available_stores(), &data, request.iterations, request.warmup).await;
+    // Every cycle ends with a clear, so the stores are empty rather than holding a payload
+    state.set_last_write(None);
+
+    Ok((StatusCode::OK, Json(json!({
+        "unit": TIMING_UNIT,
+        "iterations": request.iterations,
+        "warmup": request.warmup,
+        "rows": data.intervals.len(),
+        "bytes": encoded_len(&data),
+        "results": results,
+        "backends": backends
+    }))))
+}
+
+#[derive(Serialize)]
+struct BackendInfo {
+    name: String,
+    #[serde(flatten)]
+    status: BackendStatus,
+    capabilities: Option<Capabilities>,
+    /// Accepted `strategy` names, if the backend has more than one write path.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    write_strategies: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_write_strategy: Option<&'static str>,
+}
+
+pub async fn list_backends(State(state): State<AppState>) -> impl IntoResponse {
+    let backends: Vec<_> = state
+        .backends
+        .iter()
+        .map(|backend| {
+            let store = backend.store();
+            BackendInfo {
+                name: backend.name().to_string(),
+                status: backend.status(),
+                capabilities: store.as_ref().map(|store| store.capabilities()),
+                write_strategies: store.as_ref().map_or_else(Vec::new, |store| store.write_strategies().to_vec()),
+                default_write_strategy: store.as_ref().and_then(|store| store.default_write_strategy()),
+            }
+        })
+        .collect();
+
+    Json(json!({ "backends": backends }))
+}
+
+#[derive(Deserialize)]
+pub struct RunsParams {
+    endpoint: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Recorded runs, newest first, optionally filtered by endpoint.
+pub async fn list_runs(
+    State(state): State<AppState>,
+    Query(params): Query<RunsParams>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let records = state
+        .results
+        .list()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read results: {}", e)))?;
+    let runs: Vec<_> = records
+        .into_iter()
+        .rev()
+        .filter(|record| params.endpoint.as_ref().is_none_or(|endpoint| &record.endpoint == endpoint))
+        .take(params.limit.unwrap_or(100))
+        .collect();
+
+    Ok(Json(json!({ "runs": runs })))
+}
 
-    Ok((StatusCode::OK, Json(json!({ "timings": timings })))) // Changed to 200 OK with timings
-}
\ No newline at end of file
+pub async fn get_run(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let record = state
+        .results
+        .get(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read results: {}", e)))?
+        .ok_or((StatusCode::NOT_FOUND, format!("Run {} not found", id)))?;
+
+    Ok(Json(record))
+}
+
+#[derive(Deserialize)]
+pub struct SchemaParams {
+    /// Create (`true`) or drop (`false`) the `start_time` indexes; defaults to `SCHEMA_INDEXES`.
+    indexes: Option<bool>,
+}
+
+/// Runs `init_schema` on every available backend, timing each one.
+pub async fn init_schema(
+    State(state): State<AppState>,
+    Query(params): Query<SchemaParams>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let indexes = params.indexes.unwrap_or(state.config.schema_indexes);
+    let mut timings = Vec::new();
+    let mut errors = HashMap::new();
+    let backends = state.backend_statuses();
+
+    // Unavailable backends get the same mode when they reconnect, and later runs record it
+    for backend in &state.backends {
+        backend.set_indexes(indexes);
+    }
+    state.results.set_schema_indexes(indexes);
+
+    for store in state.available_stores() {
+        let start = Instant::now();
+        match store.init_schema(indexes).await {
+            Ok(()) => timings.push(Timing::new(store.name(), Op::Schema, start.elapsed(), 0, 0)),
+            Err(e) => {
+                errors.insert(store.name().to_string(), e.to_string());
+            }
+        }
+    }
+
+    let run_id = state
+        .record_run(NewRun {
+            endpoint: "schema-init",
+            params: HashMap::from([("indexes".to_string(), indexes.to_string())]),
+            timings: timings.clone(),
+            errors: errors.clone(),
+            ..Default::default()
+        })
+        .await;
+    check_errors(&errors)?;
+
+    Ok((StatusCode::OK, Json(json!({
+        "run_id": run_id,
+        "indexes": indexes,
+        "unit": TIMING_UNIT,
+        "timings": timing_map(&timings),
+        "details": timing_details(&timings),
+        "backends": backends
+    }))))
+}
+
+/// Reads every available backend and reports where they differ from the last written
+/// payload and from each other. Returns `409` on divergence and `500` if a read failed.
+pub async fn verify_backends(State(state): State<AppState>) -> impl IntoResponse {
+    let backends = state.backend_statuses();
+    let mut reads = Vec::new();
+    for store in state.available_stores() {
+        let read = match store.get_rune_pool().await {
+            Ok(data) => Ok(Some(data)),
+            Err(StoreError::MetaNotFound) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        };
+        reads.push((store.name().to_string(), read));
+    }
+
+    let last_write = state.last_write.read().unwrap().clone();
+    let report = verify::verify(reads, last_write.as_ref());
+    let status = if report.consistent {
+        StatusCode::OK
+    } else if report.divergent {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    (status, Json(json!({
+        "report": report,
+        "backends": backends
+    })))
+}
@@ -0,0 +1,131 @@
+use crate::db::{RunePoolStore, StoreError};
+use crate::models::rune_pool::DbRunePoolResponse;
+use crate::models::timing::Op;
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Highest latency the histograms can record (one hour, in nanoseconds).
+const MAX_TRACKABLE_NANOS: u64 = 3_600_000_000_000;
+
+/// Most warmup plus recorded cycles a single `POST /bench` may ask for.
+pub const MAX_CYCLES: u32 = 10_000;
+
+/// Latency distribution of one operation on one backend, in microseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpStats {
+    pub samples: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+}
+
+impl OpStats {
+    fn from_histogram(histogram: &Histogram<u64>) -> Self {
+        let micros = |nanos: u64| nanos as f64 / 1_000.0;
+        OpStats {
+            samples: histogram.len(),
+            min: micros(histogram.min()),
+            max: micros(histogram.max()),
+            mean: histogram.mean() / 1_000.0,
+            stddev: histogram.stdev() / 1_000.0,
+            p50: micros(histogram.value_at_quantile(0.50)),
+            p90: micros(histogram.value_at_quantile(0.90)),
+            p99: micros(histogram.value_at_quantile(0.99)),
+            p999: micros(histogram.value_at_quantile(0.999)),
+        }
+    }
+}
+
+/// Benchmark outcome for one backend; `error` is set if a cycle failed part-way.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendBench {
+    pub ops: HashMap<Op, OpStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Runs `warmup` unrecorded and then `iterations` recorded write/read/clear cycles
+/// against each store in turn.
+pub async fn run(
+    stores: &[Arc<dyn RunePoolStore>],
+    data: &DbRunePoolResponse,
+    iterations: u32,
+    warmup: u32,
+) -> HashMap<String, BackendBench> {
+    let mut results = HashMap::new();
+    for store in stores {
+        results.insert(store.name().to_string(), bench_store(store.as_ref(), data, iterations, warmup).await);
+    }
+    results
+}
+
+async fn bench_store(
+    store: &dyn RunePoolStore,
+    data: &DbRunePoolResponse,
+    iterations: u32,
+    warmup: u32,
+) -> BackendBench {
+    let mut histograms: HashMap<Op, Histogram<u64>> = [Op::Write, Op::Read, Op::Clear]
+        .into_iter()
+        .map(|op| {
+            let histogram = Histogram::new_with_bounds(1, MAX_TRACKABLE_NANOS, 3)
+                .expect("valid histogram bounds");
+            (op, histogram)
+        })
+        .collect();
+
+    let mut error = None;
+    // Counted in u64 so that no pair of u32 counts can overflow
+    for iteration in 0..u64::from(warmup) + u64::from(iterations) {
+        match cycle(store, data).await {
+            Ok(samples) if iteration >= u64::from(warmup) => {
+                for (op, nanos) in samples {
+                    histograms
+                        .get_mut(&op)
+                        .expect("histogram for every op")
+                        .saturating_record(nanos.max(1));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+
+    let ops = histograms
+        .iter()
+        .filter(|(_, histogram)| !histogram.is_empty())
+        .map(|(op, histogram)| (*op, OpStats::from_histogram(histogram)))
+        .collect();
+    BackendBench { ops, error }
+}
+
+/// One write/read/clear round trip, returning the elapsed nanoseconds of each step.
+async fn cycle(
+    store: &dyn RunePoolStore,
+    data: &DbRunePoolResponse,
+) -> Result<[(Op, u64); 3], StoreError> {
+    let start = Instant::now();
+    store.update_rune_pool(data).await?;
+    let write = start.elapsed().as_nanos() as u64;
+
+    let start = Instant::now();
+    store.get_rune_pool().await?;
+    let read = start.elapsed().as_nanos() as u64;
+
+    let start = Instant::now();
+    store.clear().await?;
+    let clear = start.elapsed().as_nanos() as u64;
+
+    Ok([(Op::Write, write), (Op::Read, read), (Op::Clear, clear)])
+}
@@ -0,0 +1,138 @@
+//! Benchmarks the database clients directly, without the HTTP server in between.
+//!
+//! ```text
+//! bench run --backends leveldb,psql --sizes 10,400,10000 --iterations 50 --output results.json
+//! ```
+
+use clap::{Args, Parser, Subcommand};
+use performance_metrics_diff_dbs::bench::{self, BackendBench};
+use performance_metrics_diff_dbs::config::Config;
+use performance_metrics_diff_dbs::db;
+use performance_metrics_diff_dbs::generator::{self, DEFAULT_SEED};
+use performance_metrics_diff_dbs::models::timing::{encoded_len, Op};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(about = "Benchmark the RunePool database backends without HTTP overhead")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run write/read/clear cycles for every backend and payload size.
+    Run(RunArgs),
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Backends to benchmark; defaults to ENABLED_BACKENDS.
+    #[arg(long, value_delimiter = ',')]
+    backends: Option<Vec<String>>,
+    /// Interval counts of the synthetic payloads.
+    #[arg(long, value_delimiter = ',', default_value = "10,400")]
+    sizes: Vec<usize>,
+    /// Seed for the synthetic payload generator.
+    #[arg(long, default_value_t = DEFAULT_SEED)]
+    seed: u64,
+    /// Recorded cycles per backend and size.
+    #[arg(long, default_value_t = 20)]
+    iterations: u32,
+    /// Unrecorded cycles run before measuring.
+    #[arg(long, default_value_t = 0)]
+    warmup: u32,
+    /// Write the full results as JSON to this file.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct SizeResult {
+    intervals: usize,
+    bytes: usize,
+    results: HashMap<String, BackendBench>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    unit: &'static str,
+    seed: u64,
+    iterations: u32,
+    warmup: u32,
+    runs: Vec<SizeResult>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let Command::Run(args) = Cli::parse().command;
+
+    let mut config = Config::load();
+    if let Some(backends) = args.backends {
+        config.enabled_backends = backends.iter().map(|name| name.trim().to_lowercase()).collect();
+    }
+
+    let mut stores = Vec::new();
+    for name in &config.enabled_backends {
+        stores.push(db::connect(name, &config).await?);
+    }
+
+    let mut runs = Vec::new();
+    for &intervals in &args.sizes {
+        let data = generator::generate(intervals, args.seed);
+        println!("== {} intervals ==", intervals);
+        let results = bench::run(&stores, &data, args.iterations, args.warmup).await;
+        print_summary(&config.enabled_backends, &results);
+        runs.push(SizeResult {
+            intervals,
+            bytes: encoded_len(&data),
+            results,
+        });
+    }
+
+    if let Some(path) = args.output {
+        let report = Report {
+            unit: "us",
+            seed: args.seed,
+            iterations: args.iterations,
+            warmup: args.warmup,
+            runs,
+        };
+        serde_json::to_writer_pretty(File::create(&path)?, &report)?;
+        println!("Results written to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn print_summary(backends: &[String], results: &HashMap<String, BackendBench>) {
+    println!(
+        "{:<10} {:<6} {:>12} {:>12} {:>12} {:>12}",
+        "backend", "op", "p50 (us)", "p99 (us)", "mean (us)", "max (us)"
+    );
+    for backend in backends {
+        let Some(result) = results.get(backend) else {
+            continue;
+        };
+        for op in [Op::Write, Op::Read, Op::Clear] {
+            if let Some(stats) = result.ops.get(&op) {
+                println!(
+                    "{:<10} {:<6} {:>12.1} {:>12.1} {:>12.1} {:>12.1}",
+                    backend,
+                    op.as_str(),
+                    stats.p50,
+                    stats.p99,
+                    stats.mean,
+                    stats.max
+                );
+            }
+        }
+        if let Some(error) = &result.error {
+            println!("{:<10} error: {}", backend, error);
+        }
+    }
+}
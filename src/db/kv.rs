@@ -0,0 +1,799 @@
+//! Key layout shared by the embedded key-value stores.
+//!
+//! Intervals live under `INTERVAL_PREFIX` followed by their `start_time` as 8 big-endian
+//! bytes, so the stores' byte ordering is chronological and a read is one forward scan.
+//! The blob layout keeps everything under `BLOB_KEY`; the chunked layout keys each chunk
+//! by its first `start_time` under `CHUNK_PREFIX`. Values are encoded with the `Codec`
+//! chosen by `KV_CODEC`.
+//!
+//! `KvStore` implements every layout once, over the few primitives each store provides
+//! through `KvBackend`.
+
+use crate::config::Config;
+use crate::db::layout::{self, Layout, MergeLock};
+use crate::db::{Capabilities, IntervalRange, RunePoolStore, StoreError, WriteSemantics};
+use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
+use crate::models::timing::Op;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+pub const META_KEY: &[u8] = b"meta";
+pub const INTERVAL_PREFIX: &[u8] = b"interval/";
+pub const BLOB_KEY: &[u8] = b"blob";
+pub const CHUNK_PREFIX: &[u8] = b"chunk/";
+
+pub fn interval_key(start_time: u64) -> Vec<u8> {
+    prefixed_key(INTERVAL_PREFIX, start_time)
+}
+
+pub fn chunk_key(first_start_time: u64) -> Vec<u8> {
+    prefixed_key(CHUNK_PREFIX, first_start_time)
+}
+
+fn prefixed_key(prefix: &[u8], start_time: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(prefix.len() + 8);
+    key.extend_from_slice(prefix);
+    key.extend_from_slice(&start_time.to_be_bytes());
+    key
+}
+
+/// Exclusive upper bound of the keys under `prefix`: the prefix with its last byte incremented.
+pub fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    *end.last_mut().expect("non-empty prefix") += 1;
+    end
+}
+
+/// A stored key and its value.
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// The operations of an ordered, byte-keyed store that `KvStore` lays the data out on.
+pub trait KvBackend: Send + Sync {
+    /// Puts and deletes collected for `write` to apply atomically.
+    type Batch;
+
+    /// Short identifier reported as the store's `name`.
+    fn name(&self) -> &'static str;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StoreError>;
+
+    /// Calls `visit` with each entry keyed `start <= key < end`, in key order, until it
+    /// returns `false`.
+    fn scan<F>(&self, start: &[u8], end: &[u8], visit: F) -> Result<(), StoreError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<bool, StoreError>;
+
+    /// The last entry under `prefix` keyed at or before `key`, found with one reverse seek.
+    fn last_at_or_before(&self, prefix: &[u8], key: &[u8]) -> Result<Option<Entry>, StoreError>;
+
+    fn batch(&self) -> Self::Batch;
+
+    fn batch_put(&self, batch: &mut Self::Batch, key: &[u8], value: &[u8]);
+
+    fn batch_delete(&self, batch: &mut Self::Batch, key: &[u8]);
+
+    /// Adds the deletion of every key under `prefix` to `batch`.
+    fn batch_delete_prefix(&self, batch: &mut Self::Batch, prefix: &[u8]);
+
+    fn write(&self, batch: Self::Batch) -> Result<(), StoreError>;
+}
+
+/// A `RunePoolStore` over a `KvBackend`, in the layout named by `LAYOUT` and the codec
+/// named by `KV_CODEC`.
+pub struct KvStore<B> {
+    backend: B,
+    codec: Codec,
+    layout: Layout,
+    merge_lock: MergeLock,
+}
+
+impl<B: KvBackend> KvStore<B> {
+    /// Reads the codec and layout from `config`, then opens the backend with `open`.
+    pub fn open(config: &Config, open: impl FnOnce() -> Result<B, StoreError>) -> Result<Self, StoreError> {
+        let codec = Codec::from_config(config)?;
+        let layout = Layout::from_config(config)?;
+        Ok(KvStore { backend: open()?, codec, layout, merge_lock: MergeLock::default() })
+    }
+
+    async fn write(&self, response: &DbRunePoolResponse, semantics: WriteSemantics) -> Result<(), StoreError> {
+        match self.layout {
+            Layout::Row => self.write_rows(response, semantics == WriteSemantics::Replace),
+            Layout::Blob => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_blob(&layout::target(self, response, semantics).await?)
+            }
+            Layout::Chunked(size) => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_chunks(&layout::target(self, response, semantics).await?, size)
+            }
+        }
+    }
+
+    /// Writes `response` in one atomic batch, first dropping every stored interval if `replace`.
+    fn write_rows(&self, response: &DbRunePoolResponse, replace: bool) -> Result<(), StoreError> {
+        let mut batch = self.backend.batch();
+        if replace {
+            self.backend.batch_delete_prefix(&mut batch, INTERVAL_PREFIX);
+        }
+
+        self.backend.batch_put(&mut batch, META_KEY, &self.codec.encode(&response.meta)?);
+        for interval in &response.intervals {
+            self.backend.batch_put(&mut batch, &interval_key(interval.start_time), &self.codec.encode(interval)?);
+        }
+
+        self.backend.write(batch)
+    }
+
+    /// Writes `data` as the single value under `BLOB_KEY`.
+    fn write_blob(&self, data: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.backend.put(BLOB_KEY, &self.codec.encode(data)?)
+    }
+
+    /// Replaces meta and every chunk with those of `data` in one atomic batch.
+    fn write_chunks(&self, data: &DbRunePoolResponse, size: usize) -> Result<(), StoreError> {
+        let mut batch = self.backend.batch();
+        self.backend.batch_delete_prefix(&mut batch, CHUNK_PREFIX);
+        self.backend.batch_put(&mut batch, META_KEY, &self.codec.encode(&data.meta)?);
+        for chunk in layout::chunks(&data.intervals, size) {
+            self.backend.batch_put(&mut batch, &chunk_key(chunk.first_start_time), &self.codec.encode(&chunk.intervals)?);
+        }
+        self.backend.write(batch)
+    }
+
+    fn read(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        match self.layout {
+            Layout::Row => self.read_rows(range),
+            Layout::Blob => {
+                let value = self.backend.get(BLOB_KEY)?.ok_or(StoreError::MetaNotFound)?;
+                let data: DbRunePoolResponse = self.codec.decode(&value)?;
+                Ok(DbRunePoolResponse {
+                    meta: data.meta,
+                    intervals: layout::select(data.intervals, range),
+                })
+            }
+            Layout::Chunked(_) => {
+                let meta = self.read_meta()?;
+                // Start at the chunk holding `from`, the last one keyed at or before it
+                let start = self
+                    .backend
+                    .last_at_or_before(CHUNK_PREFIX, &chunk_key(range.from.unwrap_or(0)))?
+                    .map_or_else(|| CHUNK_PREFIX.to_vec(), |(key, _)| key);
+                let end = range.to.map_or_else(|| prefix_end(CHUNK_PREFIX), chunk_key);
+                let mut intervals = Vec::new();
+                self.backend.scan(&start, &end, |_, value| {
+                    let chunk: Vec<DbInterval> = self.codec.decode(value)?;
+                    intervals.extend(chunk);
+                    Ok(true)
+                })?;
+                Ok(DbRunePoolResponse {
+                    meta,
+                    intervals: layout::select(intervals, range),
+                })
+            }
+        }
+    }
+
+    fn read_meta(&self) -> Result<DbMeta, StoreError> {
+        let meta_value = self.backend.get(META_KEY)?.ok_or(StoreError::MetaNotFound)?;
+        self.codec.decode(&meta_value)
+    }
+
+    /// Reads meta and the intervals in `range` with one forward scan; keys sort by start_time.
+    fn read_rows(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let meta = self.read_meta()?;
+
+        // Seek to `from` and stop at the first key at or past `to`, or once `limit` are read
+        let start = interval_key(range.from.unwrap_or(0));
+        let end = range.to.map_or_else(|| prefix_end(INTERVAL_PREFIX), interval_key);
+        let limit = range.limit.unwrap_or(usize::MAX);
+        let mut skip = range.offset;
+        let mut intervals = Vec::new();
+        self.backend.scan(&start, &end, |_, value| {
+            if skip > 0 {
+                skip -= 1;
+            } else if intervals.len() < limit {
+                let interval: DbInterval = self.codec.decode(value)?;
+                intervals.push(interval);
+            }
+            Ok(intervals.len() < limit)
+        })?;
+
+        Ok(DbRunePoolResponse { meta, intervals })
+    }
+}
+
+#[async_trait]
+impl<B: KvBackend> RunePoolStore for KvStore<B> {
+    fn name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            embedded: true,
+            transactions: false,
+        }
+    }
+
+    fn settings(&self, op: Op) -> BTreeMap<String, String> {
+        let mut settings = self.codec.settings(op);
+        settings.extend(self.layout.settings(op));
+        settings
+    }
+
+    fn encoded_len(&self, response: &DbRunePoolResponse) -> Option<usize> {
+        self.codec.encoded_len(response, self.layout).ok()
+    }
+
+    /// Keys are ordered by start_time already, so there is no schema or index to manage.
+    async fn init_schema(&self, _indexes: bool) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    async fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.write(response, WriteSemantics::Replace).await
+    }
+
+    async fn upsert_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        // Keys are derived from start_time, so a put overwrites the matching interval
+        self.write(response, WriteSemantics::Upsert).await
+    }
+
+    async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, StoreError> {
+        self.read(&IntervalRange::default())
+    }
+
+    async fn get_range(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        self.read(range)
+    }
+
+    async fn get_meta(&self) -> Result<DbMeta, StoreError> {
+        match self.layout {
+            Layout::Row | Layout::Chunked(_) => self.read_meta(),
+            Layout::Blob => Ok(self.read(&IntervalRange::default())?.meta),
+        }
+    }
+
+    async fn get_last_interval(&self) -> Result<Option<DbInterval>, StoreError> {
+        if self.layout != Layout::Row {
+            return Ok(self.read(&IntervalRange::default())?.intervals.pop());
+        }
+        match self.backend.last_at_or_before(INTERVAL_PREFIX, &prefix_end(INTERVAL_PREFIX))? {
+            Some((_, value)) => Ok(Some(self.codec.decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn clear(&self) -> Result<(), StoreError> {
+        let mut batch = self.backend.batch();
+        match self.layout {
+            Layout::Row => {
+                self.backend.batch_delete(&mut batch, META_KEY);
+                self.backend.batch_delete_prefix(&mut batch, INTERVAL_PREFIX);
+            }
+            Layout::Blob => self.backend.batch_delete(&mut batch, BLOB_KEY),
+            Layout::Chunked(_) => {
+                self.backend.batch_delete(&mut batch, META_KEY);
+                self.backend.batch_delete_prefix(&mut batch, CHUNK_PREFIX);
+            }
+        }
+        self.backend.write(batch)
+    }
+}
+
+/// How the key-value stores encode their values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Json,
+    Bincode,
+    MessagePack,
+    Cbor,
+    Postcard,
+    /// Every field as a little-endian `u64`, in declaration order.
+    Fixed,
+}
+
+impl Codec {
+    pub const NAMES: &'static [&'static str] = &["json", "bincode", "msgpack", "cbor", "postcard", "fixed"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::Bincode => "bincode",
+            Codec::MessagePack => "msgpack",
+            Codec::Cbor => "cbor",
+            Codec::Postcard => "postcard",
+            Codec::Fixed => "fixed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Codec::Json),
+            "bincode" => Some(Codec::Bincode),
+            "msgpack" => Some(Codec::MessagePack),
+            "cbor" => Some(Codec::Cbor),
+            "postcard" => Some(Codec::Postcard),
+            "fixed" => Some(Codec::Fixed),
+            _ => None,
+        }
+    }
+
+    /// The codec named by `KV_CODEC`.
+    pub fn from_config(config: &Config) -> Result<Self, StoreError> {
+        Codec::from_name(&config.kv_codec).ok_or_else(|| {
+            StoreError::backend(format!(
+                "unknown KV codec `{}`, expected one of {}",
+                config.kv_codec,
+                Codec::NAMES.join(", ")
+            ))
+        })
+    }
+
+    pub fn encode<T: FixedLayout>(&self, value: &T) -> Result<Vec<u8>, StoreError> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            Codec::Bincode => bincode::serialize(value).map_err(|e| self.error(e)),
+            Codec::MessagePack => rmp_serde::to_vec(value).map_err(|e| self.error(e)),
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| self.error(e))?;
+                Ok(bytes)
+            }
+            Codec::Postcard => postcard::to_allocvec(value).map_err(|e| self.error(e)),
+            Codec::Fixed => Ok(value.to_fields().iter().flat_map(|field| field.to_le_bytes()).collect()),
+        }
+    }
+
+    pub fn decode<T: FixedLayout>(&self, bytes: &[u8]) -> Result<T, StoreError> {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            Codec::Bincode => bincode::deserialize(bytes).map_err(|e| self.error(e)),
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| self.error(e)),
+            Codec::Cbor => ciborium::from_reader(bytes).map_err(|e| self.error(e)),
+            Codec::Postcard => postcard::from_bytes(bytes).map_err(|e| self.error(e)),
+            Codec::Fixed => {
+                if !bytes.len().is_multiple_of(8) {
+                    return Err(self.error(format!("{} bytes is not a whole number of fields", bytes.len())));
+                }
+                let fields: Vec<u64> = bytes
+                    .chunks_exact(8)
+                    .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("8-byte chunk")))
+                    .collect();
+                T::from_fields(&fields).ok_or_else(|| self.error(format!("unexpected field count {}", fields.len())))
+            }
+        }
+    }
+
+    /// Total size of the values `response` is stored as under `layout`.
+    pub fn encoded_len(&self, response: &DbRunePoolResponse, layout: Layout) -> Result<usize, StoreError> {
+        match layout {
+            Layout::Row => {
+                let mut len = self.encode(&response.meta)?.len();
+                for interval in &response.intervals {
+                    len += self.encode(interval)?.len();
+                }
+                Ok(len)
+            }
+            Layout::Blob => Ok(self.encode(response)?.len()),
+            Layout::Chunked(size) => {
+                let mut len = self.encode(&response.meta)?.len();
+                for chunk in layout::chunks(&response.intervals, size) {
+                    len += self.encode(&chunk.intervals)?.len();
+                }
+                Ok(len)
+            }
+        }
+    }
+
+    /// `kv_codec`, reported with read and write timings.
+    pub fn settings(&self, op: Op) -> BTreeMap<String, String> {
+        match op {
+            Op::Write | Op::Read => BTreeMap::from([("codec".to_string(), self.as_str().to_string())]),
+            Op::Clear | Op::Schema => BTreeMap::new(),
+        }
+    }
+
+    fn error(&self, err: impl Display) -> StoreError {
+        StoreError::Encoding {
+            codec: self.as_str(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A stored value made of `u64` fields, so it has a fixed-width encoding.
+pub trait FixedLayout: Serialize + DeserializeOwned {
+    fn to_fields(&self) -> Vec<u64>;
+
+    /// Builds the value, or `None` if `fields` has the wrong length for it.
+    fn from_fields(fields: &[u64]) -> Option<Self>;
+}
+
+/// Fields per value in the fixed encoding.
+const META_FIELDS: usize = 6;
+const INTERVAL_FIELDS: usize = 4;
+
+impl FixedLayout for DbMeta {
+    fn to_fields(&self) -> Vec<u64> {
+        vec![
+            self.start_time,
+            self.end_time,
+            self.start_count,
+            self.end_count,
+            self.start_units,
+            self.end_units,
+        ]
+    }
+
+    fn from_fields(fields: &[u64]) -> Option<Self> {
+        if fields.len() != META_FIELDS {
+            return None;
+        }
+        Some(DbMeta {
+            start_time: fields[0],
+            end_time: fields[1],
+            start_count: fields[2],
+            end_count: fields[3],
+            start_units: fields[4],
+            end_units: fields[5],
+        })
+    }
+}
+
+impl FixedLayout for DbInterval {
+    fn to_fields(&self) -> Vec<u64> {
+        vec![self.start_time, self.end_time, self.count, self.units]
+    }
+
+    fn from_fields(fields: &[u64]) -> Option<Self> {
+        if fields.len() != INTERVAL_FIELDS {
+            return None;
+        }
+        Some(DbInterval {
+            start_time: fields[0],
+            end_time: fields[1],
+            count: fields[2],
+            units: fields[3],
+        })
+    }
+}
+
+/// The intervals of a chunk, back to back.
+impl FixedLayout for Vec<DbInterval> {
+    fn to_fields(&self) -> Vec<u64> {
+        self.iter().flat_map(FixedLayout::to_fields).collect()
+    }
+
+    fn from_fields(fields: &[u64]) -> Option<Self> {
+        if !fields.len().is_multiple_of(INTERVAL_FIELDS) {
+            return None;
+        }
+        fields.chunks_exact(INTERVAL_FIELDS).map(DbInterval::from_fields).collect()
+    }
+}
+
+/// The meta followed by the intervals, as a blob.
+impl FixedLayout for DbRunePoolResponse {
+    fn to_fields(&self) -> Vec<u64> {
+        let mut fields = self.meta.to_fields();
+        fields.extend(self.intervals.to_fields());
+        fields
+    }
+
+    fn from_fields(fields: &[u64]) -> Option<Self> {
+        if fields.len() < META_FIELDS {
+            return None;
+        }
+        let (meta, intervals) = fields.split_at(META_FIELDS);
+        Some(DbRunePoolResponse {
+            meta: DbMeta::from_fields(meta)?,
+            intervals: Vec::from_fields(intervals)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::generator::{generate, generate_with, GeneratorOptions};
+    use std::sync::Mutex;
+
+    fn json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).expect("serializable")
+    }
+
+    /// An in-memory `KvBackend`, to run the layouts without an embedded store.
+    #[derive(Default)]
+    struct MemoryKv(Mutex<BTreeMap<Vec<u8>, Vec<u8>>>);
+
+    enum BatchOp {
+        Put(Vec<u8>, Vec<u8>),
+        Delete(Vec<u8>),
+        DeletePrefix(Vec<u8>),
+    }
+
+    impl KvBackend for MemoryKv {
+        type Batch = Vec<BatchOp>;
+
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+            self.0.lock().unwrap().insert(key.to_vec(), value.to_vec());
+            Ok(())
+        }
+
+        fn scan<F>(&self, start: &[u8], end: &[u8], mut visit: F) -> Result<(), StoreError>
+        where
+            F: FnMut(&[u8], &[u8]) -> Result<bool, StoreError>,
+        {
+            for (key, value) in self.0.lock().unwrap().range(start.to_vec()..end.to_vec()) {
+                if !visit(key, value)? {
+                    break;
+                }
+            }
+            Ok(())
+        }
+
+        fn last_at_or_before(&self, prefix: &[u8], key: &[u8]) -> Result<Option<Entry>, StoreError> {
+            let map = self.0.lock().unwrap();
+            let found = map.range(prefix.to_vec()..=key.to_vec()).next_back();
+            Ok(found.map(|(key, value)| (key.clone(), value.clone())))
+        }
+
+        fn batch(&self) -> Self::Batch {
+            Vec::new()
+        }
+
+        fn batch_put(&self, batch: &mut Self::Batch, key: &[u8], value: &[u8]) {
+            batch.push(BatchOp::Put(key.to_vec(), value.to_vec()));
+        }
+
+        fn batch_delete(&self, batch: &mut Self::Batch, key: &[u8]) {
+            batch.push(BatchOp::Delete(key.to_vec()));
+        }
+
+        fn batch_delete_prefix(&self, batch: &mut Self::Batch, prefix: &[u8]) {
+            batch.push(BatchOp::DeletePrefix(prefix.to_vec()));
+        }
+
+        fn write(&self, batch: Self::Batch) -> Result<(), StoreError> {
+            let mut map = self.0.lock().unwrap();
+            for op in batch {
+                match op {
+                    BatchOp::Put(key, value) => {
+                        map.insert(key, value);
+                    }
+                    BatchOp::Delete(key) => {
+                        map.remove(&key);
+                    }
+                    BatchOp::DeletePrefix(prefix) => map.retain(|key, _| !key.starts_with(&prefix)),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Wraps a backend so that writing a batch of more than `after` puts fails instead.
+    struct FailAfterPuts<B> {
+        inner: B,
+        after: usize,
+    }
+
+    impl<B: KvBackend> KvBackend for FailAfterPuts<B> {
+        /// The inner batch and the number of puts in it.
+        type Batch = (B::Batch, usize);
+
+        fn name(&self) -> &'static str {
+            self.inner.name()
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+            self.inner.get(key)
+        }
+
+        fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+            self.inner.put(key, value)
+        }
+
+        fn scan<F>(&self, start: &[u8], end: &[u8], visit: F) -> Result<(), StoreError>
+        where
+            F: FnMut(&[u8], &[u8]) -> Result<bool, StoreError>,
+        {
+            self.inner.scan(start, end, visit)
+        }
+
+        fn last_at_or_before(&self, prefix: &[u8], key: &[u8]) -> Result<Option<Entry>, StoreError> {
+            self.inner.last_at_or_before(prefix, key)
+        }
+
+        fn batch(&self) -> Self::Batch {
+            (self.inner.batch(), 0)
+        }
+
+        fn batch_put(&self, batch: &mut Self::Batch, key: &[u8], value: &[u8]) {
+            batch.1 += 1;
+            self.inner.batch_put(&mut batch.0, key, value);
+        }
+
+        fn batch_delete(&self, batch: &mut Self::Batch, key: &[u8]) {
+            self.inner.batch_delete(&mut batch.0, key);
+        }
+
+        fn batch_delete_prefix(&self, batch: &mut Self::Batch, prefix: &[u8]) {
+            self.inner.batch_delete_prefix(&mut batch.0, prefix);
+        }
+
+        fn write(&self, batch: Self::Batch) -> Result<(), StoreError> {
+            if batch.1 > self.after {
+                return Err(StoreError::backend(format!("injected fault after {} puts", self.after)));
+            }
+            self.inner.write(batch.0)
+        }
+    }
+
+    /// Asserts that a replace whose row batch fails leaves the previous dataset stored in
+    /// `backend` in place.
+    pub(crate) async fn assert_fault_keeps_previous<B: KvBackend>(backend: B) {
+        let store = KvStore::open(&config("row"), || Ok(FailAfterPuts { inner: backend, after: 500 })).expect("open");
+        let previous = dataset(100, 1, 0);
+        store.update_rune_pool(&previous).await.expect("write previous");
+
+        // Shares no start_time with previous, so any trace of it is visible
+        let next = dataset(1_000, 2, 1_000);
+        let result = store.update_rune_pool(&next).await;
+        assert!(result.is_err(), "{}: the injected fault did not fail the write", store.name());
+
+        let stored = store.get_rune_pool().await.expect("read");
+        let diff = crate::verify::diff(Some(&previous), Some(&stored), false);
+        assert!(
+            diff.is_empty(),
+            "{}: stored data changed: {} missing, {} extra intervals",
+            store.name(),
+            diff.missing.len(),
+            diff.extra.len()
+        );
+    }
+
+    fn config(layout: &str) -> Config {
+        let mut config = Config::load();
+        config.layout = layout.to_string();
+        config.chunk_size = 7;
+        config.kv_codec = "json".to_string();
+        config
+    }
+
+    fn dataset(intervals: usize, seed: u64, offset: u64) -> DbRunePoolResponse {
+        let mut options = GeneratorOptions::new(intervals, seed);
+        options.start_time += offset * options.interval_secs;
+        generate_with(&options)
+    }
+
+    fn start_times(intervals: &[DbInterval]) -> Vec<u64> {
+        intervals.iter().map(|i| i.start_time).collect()
+    }
+
+    #[tokio::test]
+    async fn failed_row_write_keeps_previous_data() {
+        assert_fault_keeps_previous(MemoryKv::default()).await;
+    }
+
+    #[tokio::test]
+    async fn every_layout_round_trips() {
+        for layout in Layout::NAMES {
+            let store = KvStore::open(&config(layout), || Ok(MemoryKv::default())).expect("open");
+            store.update_rune_pool(&dataset(20, 1, 100)).await.expect("write stale");
+
+            let replacement = dataset(30, 2, 0);
+            store.update_rune_pool(&replacement).await.expect("replace");
+            let stored = store.get_rune_pool().await.expect("read");
+            assert_eq!(json(&stored), json(&replacement), "{}: replace", layout);
+
+            // Overwrites the last ten intervals and adds ten more
+            let update = dataset(20, 3, 20);
+            store.upsert_rune_pool(&update).await.expect("upsert");
+            let mut expected = replacement.intervals[..20].to_vec();
+            expected.extend(update.intervals.iter().cloned());
+            let stored = store.get_rune_pool().await.expect("read");
+            assert_eq!(json(&stored.meta), json(&update.meta), "{}: upsert meta", layout);
+            assert_eq!(json(&stored.intervals), json(&expected), "{}: upsert", layout);
+
+            let times = start_times(&expected);
+            let range = IntervalRange {
+                from: Some(times[5] + 1),
+                to: Some(times[31]),
+                limit: Some(12),
+                offset: 3,
+            };
+            let selected = store.get_range(&range).await.expect("range");
+            assert_eq!(start_times(&selected.intervals), times[9..21], "{}: range", layout);
+
+            let last = store.get_last_interval().await.expect("last").expect("an interval");
+            assert_eq!(json(&last), json(expected.last().unwrap()), "{}: last interval", layout);
+            assert_eq!(json(&store.get_meta().await.expect("meta")), json(&update.meta));
+
+            store.clear().await.expect("clear");
+            assert!(matches!(store.get_rune_pool().await, Err(StoreError::MetaNotFound)), "{}: clear", layout);
+        }
+    }
+
+    fn round_trip<T: FixedLayout>(codec: Codec, value: &T) {
+        let decoded: T = codec.decode(&codec.encode(value).expect("encode")).expect("decode");
+        assert_eq!(json(&decoded), json(value), "{} changed the value", codec.as_str());
+    }
+
+    #[test]
+    fn every_codec_round_trips_every_stored_value() {
+        let data = generate(250, 7);
+        for name in Codec::NAMES {
+            let codec = Codec::from_name(name).expect("listed codec");
+            assert_eq!(codec.as_str(), *name);
+            round_trip(codec, &data.meta);
+            round_trip(codec, &data.intervals[0]);
+            round_trip(codec, &data.intervals[..100].to_vec());
+            round_trip(codec, &data);
+        }
+    }
+
+    #[test]
+    fn fixed_writes_little_endian_fields_in_order() {
+        let interval = DbInterval {
+            start_time: 1,
+            end_time: 2,
+            count: 3,
+            units: u64::MAX,
+        };
+        let bytes = Codec::Fixed.encode(&interval).expect("encode");
+        assert_eq!(bytes.len(), INTERVAL_FIELDS * 8);
+        assert_eq!(bytes[..8], 1u64.to_le_bytes());
+        assert_eq!(bytes[24..], u64::MAX.to_le_bytes());
+    }
+
+    #[test]
+    fn fixed_rejects_a_partial_field() {
+        let err = Codec::Fixed.decode::<DbInterval>(&[0; 33]).unwrap_err();
+        assert!(
+            matches!(&err, StoreError::Encoding { codec: "fixed", message } if message.contains("whole number")),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn fixed_rejects_the_wrong_field_count() {
+        let five_fields = [0; 5 * 8];
+        assert!(Codec::Fixed.decode::<DbMeta>(&five_fields).is_err());
+        assert!(Codec::Fixed.decode::<DbInterval>(&five_fields).is_err());
+        assert!(Codec::Fixed.decode::<Vec<DbInterval>>(&five_fields).is_err());
+        assert!(Codec::Fixed.decode::<DbRunePoolResponse>(&five_fields).is_err());
+        // A meta followed by part of an interval
+        assert!(Codec::Fixed.decode::<DbRunePoolResponse>(&[0; 8 * 8]).is_err());
+    }
+
+    #[test]
+    fn fixed_encoded_len_is_the_same_under_every_layout() {
+        let data = generate(250, 7);
+        let expected = (META_FIELDS + 250 * INTERVAL_FIELDS) * 8;
+        for layout in [Layout::Row, Layout::Blob, Layout::Chunked(100)] {
+            assert_eq!(Codec::Fixed.encoded_len(&data, layout).expect("encode"), expected);
+        }
+    }
+
+    #[test]
+    fn keys_sort_by_start_time() {
+        assert!(interval_key(255) < interval_key(256));
+        assert!(chunk_key(u64::MAX) < prefix_end(CHUNK_PREFIX));
+        assert!(interval_key(0) > INTERVAL_PREFIX.to_vec());
+    }
+}
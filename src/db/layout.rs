@@ -0,0 +1,197 @@
+//! Storage layouts: how a backend maps a `DbRunePoolResponse` onto its records.
+//!
+//! `row` is the native one-record-per-interval model. `blob` and `chunked` keep intervals
+//! together, so they are always written whole: an upsert reads the stored data, merges
+//! the new intervals in by `start_time` and writes the result back, holding the store's
+//! `MergeLock` from the read to the write.
+
+use crate::config::Config;
+use crate::db::{IntervalRange, RunePoolStore, StoreError, WriteSemantics};
+use crate::models::rune_pool::{DbInterval, DbRunePoolResponse};
+use crate::models::timing::Op;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// One record per interval plus one meta record.
+    Row,
+    /// The whole response as one value, document, record or row.
+    Blob,
+    /// One meta record plus one record per run of this many consecutive intervals.
+    Chunked(usize),
+}
+
+impl Layout {
+    pub const NAMES: &'static [&'static str] = &["row", "blob", "chunked"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layout::Row => "row",
+            Layout::Blob => "blob",
+            Layout::Chunked(_) => "chunked",
+        }
+    }
+
+    /// The layout named by `LAYOUT`, with `CHUNK_SIZE` intervals per chunk.
+    pub fn from_config(config: &Config) -> Result<Self, StoreError> {
+        match config.layout.as_str() {
+            "row" => Ok(Layout::Row),
+            "blob" => Ok(Layout::Blob),
+            "chunked" if config.chunk_size > 0 => Ok(Layout::Chunked(config.chunk_size)),
+            "chunked" => Err(StoreError::backend("CHUNK_SIZE must be at least 1")),
+            other => Err(StoreError::backend(format!(
+                "unknown layout `{}`, expected one of {}",
+                other,
+                Layout::NAMES.join(", ")
+            ))),
+        }
+    }
+
+    /// `layout` and `chunk_size`, reported with read and write timings.
+    pub fn settings(&self, op: Op) -> BTreeMap<String, String> {
+        let mut settings = BTreeMap::new();
+        if matches!(op, Op::Write | Op::Read) {
+            settings.insert("layout".to_string(), self.as_str().to_string());
+            if let Layout::Chunked(size) = self {
+                settings.insert("chunk_size".to_string(), size.to_string());
+            }
+        }
+        settings
+    }
+}
+
+/// Serializes a store's whole-dataset writes, so two concurrent upserts cannot both merge
+/// into the same stored data and drop each other's intervals. It only guards writers in
+/// this process.
+pub type MergeLock = tokio::sync::Mutex<()>;
+
+/// A run of consecutive intervals stored as one record by the chunked layout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbChunk {
+    pub first_start_time: u64,
+    pub last_start_time: u64,
+    pub intervals: Vec<DbInterval>,
+}
+
+/// Splits `intervals`, already in `start_time` order, into chunks of at most `size`.
+pub fn chunks(intervals: &[DbInterval], size: usize) -> Vec<DbChunk> {
+    intervals
+        .chunks(size)
+        .map(|run| DbChunk {
+            first_start_time: run[0].start_time,
+            last_start_time: run[run.len() - 1].start_time,
+            intervals: run.to_vec(),
+        })
+        .collect()
+}
+
+/// The data a whole-dataset layout should hold after writing `response` with `semantics`:
+/// its intervals in `start_time` order, merged into the stored ones for an upsert.
+pub async fn target(
+    store: &dyn RunePoolStore,
+    response: &DbRunePoolResponse,
+    semantics: WriteSemantics,
+) -> Result<DbRunePoolResponse, StoreError> {
+    let mut intervals = BTreeMap::new();
+    if semantics == WriteSemantics::Upsert {
+        match store.get_rune_pool().await {
+            Ok(stored) => intervals.extend(stored.intervals.into_iter().map(|i| (i.start_time, i))),
+            Err(StoreError::MetaNotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    intervals.extend(response.intervals.iter().map(|i| (i.start_time, i.clone())));
+
+    Ok(DbRunePoolResponse {
+        meta: response.meta.clone(),
+        intervals: intervals.into_values().collect(),
+    })
+}
+
+/// The intervals `range` selects from `intervals`, which are in `start_time` order. Chunked
+/// reads fetch every chunk that overlaps `range` and leave it to this to drop the intervals
+/// of those chunks that fall outside it.
+pub fn select(intervals: impl IntoIterator<Item = DbInterval>, range: &IntervalRange) -> Vec<DbInterval> {
+    let from = range.from.unwrap_or(0);
+    let to = range.to.unwrap_or(u64::MAX);
+    intervals
+        .into_iter()
+        .filter(|i| i.start_time >= from && i.start_time < to)
+        .skip(range.offset)
+        .take(range.limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::generate;
+
+    fn start_times(intervals: &[DbInterval]) -> Vec<u64> {
+        intervals.iter().map(|i| i.start_time).collect()
+    }
+
+    #[test]
+    fn chunks_split_in_order_with_a_short_last_chunk() {
+        let data = generate(250, 7);
+        let chunks = chunks(&data.intervals, 100);
+        assert_eq!(chunks.iter().map(|c| c.intervals.len()).collect::<Vec<_>>(), [100, 100, 50]);
+        for chunk in &chunks {
+            assert_eq!(chunk.first_start_time, chunk.intervals[0].start_time);
+            assert_eq!(chunk.last_start_time, chunk.intervals.last().unwrap().start_time);
+        }
+        let rejoined: Vec<_> = chunks.into_iter().flat_map(|c| c.intervals).collect();
+        assert_eq!(start_times(&rejoined), start_times(&data.intervals));
+        assert!(super::chunks(&[], 100).is_empty());
+    }
+
+    #[test]
+    fn select_keeps_from_up_to_to_then_pages() {
+        let data = generate(10, 7);
+        let times = start_times(&data.intervals);
+        let range = |from: Option<u64>, to: Option<u64>, limit: Option<usize>, offset: usize| IntervalRange {
+            from,
+            to,
+            limit,
+            offset,
+        };
+
+        let all = select(data.intervals.clone(), &IntervalRange::default());
+        assert_eq!(start_times(&all), times);
+
+        // `from` is inclusive and `to` exclusive
+        let bounded = select(data.intervals.clone(), &range(Some(times[2]), Some(times[5]), None, 0));
+        assert_eq!(start_times(&bounded), times[2..5]);
+
+        // Between two start times, `from` starts at the next interval
+        let between = select(data.intervals.clone(), &range(Some(times[2] + 1), None, None, 0));
+        assert_eq!(start_times(&between), times[3..]);
+
+        let paged = select(data.intervals.clone(), &range(Some(times[1]), None, Some(3), 2));
+        assert_eq!(start_times(&paged), times[3..6]);
+
+        assert!(select(data.intervals.clone(), &range(Some(times[5]), Some(times[5]), None, 0)).is_empty());
+        assert!(select(data.intervals, &range(None, None, None, 10)).is_empty());
+    }
+
+    #[test]
+    fn from_config_reads_layout_and_chunk_size() {
+        let mut config = Config::load();
+        config.layout = "chunked".to_string();
+        config.chunk_size = 25;
+        assert_eq!(Layout::from_config(&config).unwrap(), Layout::Chunked(25));
+
+        config.chunk_size = 0;
+        assert!(Layout::from_config(&config).is_err());
+
+        config.layout = "columnar".to_string();
+        assert!(Layout::from_config(&config).is_err());
+
+        for name in Layout::NAMES {
+            config.layout = name.to_string();
+            config.chunk_size = 1;
+            assert_eq!(Layout::from_config(&config).unwrap().as_str(), *name);
+        }
+    }
+}
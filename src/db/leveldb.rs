@@ -1,94 +1,144 @@
 use crate::config::Config;
-use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
+use crate::db::kv::{prefix_end, Entry, KvBackend, KvStore};
+use crate::db::StoreError;
+use leveldb::batch::{Batch, Writebatch};
 use leveldb::database::Database;
+use leveldb::iterator::{Iterable, LevelDBIterator};
 use leveldb::kv::KV;
 use leveldb::options::{Options, ReadOptions, WriteOptions};
-use serde_json;
-use std::error::Error;
 use std::path::Path;
 
-pub struct LevelDBClient {
-    db: Database<i32>, // Use i32 as the key type for simplicity
-}
+/// LevelDB, laid out by the shared key-value store.
+pub type LevelDBClient = KvStore<LevelDB>;
 
 impl LevelDBClient {
     /// Initializes a new LevelDB instance with the given config.
-    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+    pub fn new(config: &Config) -> Result<Self, StoreError> {
+        KvStore::open(config, || LevelDB::open(&config.leveldb_path))
+    }
+}
+
+/// Raw byte key, so LevelDB orders keys the same way RocksDB does.
+pub struct ByteKey(Vec<u8>);
+
+impl db_key::Key for ByteKey {
+    fn from_u8(key: &[u8]) -> Self {
+        ByteKey(key.to_vec())
+    }
+
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(&self.0)
+    }
+}
+
+pub struct LevelDB {
+    db: Database<ByteKey>,
+}
+
+impl From<leveldb::error::Error> for StoreError {
+    fn from(err: leveldb::error::Error) -> Self {
+        StoreError::backend(err)
+    }
+}
+
+impl LevelDB {
+    pub fn open(path: &str) -> Result<Self, StoreError> {
         let mut opts = Options::new();
         opts.create_if_missing = true; // Create the DB if it doesn’t exist
-        let db = Database::open(Path::new(&config.leveldb_path), opts)?;
-        Ok(LevelDBClient { db })
+        let db = Database::open(Path::new(path), opts)?;
+        Ok(LevelDB { db })
+    }
+
+    /// Keys under `prefix`, found with one forward scan from the prefix.
+    fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<ByteKey> {
+        let start = ByteKey(prefix.to_vec());
+        let end = prefix_end(prefix);
+        self.db
+            .keys_iter(ReadOptions::new())
+            .from(&start)
+            .take_while(|key| key.0 < end)
+            .collect()
     }
+}
 
-    /// Updates the database with a DbRunePoolResponse.
-    pub fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), Box<dyn Error>> {
-        let write_opts = WriteOptions::new();
+impl KvBackend for LevelDB {
+    type Batch = Writebatch<ByteKey>;
 
-        // Serialize and store meta
-        let meta_key = 0; // Use a fixed key for meta
-        let meta_value = serde_json::to_vec(&response.meta)?;
-        self.db.put(write_opts, meta_key, &meta_value)?;
+    fn name(&self) -> &'static str {
+        "leveldb"
+    }
 
-        // Serialize and store each interval with a unique key
-        let write_opts = WriteOptions::new(); // Recreate for each call or scope
-        for (index, interval) in response.intervals.iter().enumerate() {
-            let key = index as i32 + 1; // Start keys from 1 to avoid clashing with meta
-            let value = serde_json::to_vec(interval)?;
-            self.db.put(write_opts, key, &value)?;
-        }
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.db.get(ReadOptions::new(), ByteKey(key.to_vec()))?)
+    }
 
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.db.put(WriteOptions::new(), ByteKey(key.to_vec()), value)?;
         Ok(())
     }
 
-    /// Retrieves the stored DbRunePoolResponse from the database.
-    pub fn get_rune_pool(&self) -> Result<DbRunePoolResponse, Box<dyn Error>> {
-        let read_opts = ReadOptions::new();
-
-        // Retrieve meta
-        let meta_key = 0;
-        let meta_value = self.db.get(read_opts, meta_key)?.ok_or("Meta not found")?;
-        let meta: DbMeta = serde_json::from_slice(&meta_value)?;
-
-        // Retrieve intervals
-        let mut intervals = Vec::new();
-        let mut index = 1; // Start from 1 since meta uses 0
-        loop {
-            let read_opts = ReadOptions::new(); // Recreate for each call
-            let key = index as i32;
-            match self.db.get(read_opts, key)? {
-                Some(value) => {
-                    let interval: DbInterval = serde_json::from_slice(&value)?;
-                    intervals.push(interval);
-                    index += 1;
-                }
-                None => break, // Stop when no more intervals are found
+    fn scan<F>(&self, start: &[u8], end: &[u8], mut visit: F) -> Result<(), StoreError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<bool, StoreError>,
+    {
+        let start = ByteKey(start.to_vec());
+        for (key, value) in self.db.iter(ReadOptions::new()).from(&start) {
+            if key.0.as_slice() >= end || !visit(&key.0, &value)? {
+                break;
             }
         }
+        Ok(())
+    }
+
+    /// A reverse iterator starts at the first key at or after `key`, which exists whenever
+    /// data does since `META_KEY` sorts after both prefixes, and steps back from there.
+    fn last_at_or_before(&self, prefix: &[u8], key: &[u8]) -> Result<Option<Entry>, StoreError> {
+        let target = ByteKey(key.to_vec());
+        Ok(self
+            .db
+            .iter(ReadOptions::new())
+            .reverse()
+            .from(&target)
+            .find(|(found, _)| found.0.as_slice() <= key)
+            .filter(|(found, _)| found.0.starts_with(prefix))
+            .map(|(found, value)| (found.0, value)))
+    }
 
-        Ok(DbRunePoolResponse { meta, intervals })
+    fn batch(&self) -> Self::Batch {
+        Writebatch::new()
     }
 
-    /// Clears all data from the database.
-pub fn clear(&self) -> Result<(), Box<dyn Error>> {
-    let write_opts = WriteOptions::new();
+    fn batch_put(&self, batch: &mut Self::Batch, key: &[u8], value: &[u8]) {
+        batch.put(ByteKey(key.to_vec()), value);
+    }
 
-    // Delete meta (key 0)
-    self.db.delete(write_opts, 0)?;
+    fn batch_delete(&self, batch: &mut Self::Batch, key: &[u8]) {
+        batch.delete(ByteKey(key.to_vec()));
+    }
 
-    // Delete all intervals (keys 1, 2, 3, ...)
-    let mut index = 1;
-    loop {
-        let read_opts = ReadOptions::new(); // Create a new ReadOptions instance in each iteration
-        let key = index as i32;
-        if self.db.get(read_opts, key)?.is_some() {
-            self.db.delete(write_opts, key)?;
-            index += 1;
-        } else {
-            break;
+    /// LevelDB has no range delete, so the keys are deleted one by one in the batch.
+    fn batch_delete_prefix(&self, batch: &mut Self::Batch, prefix: &[u8]) {
+        for key in self.keys_with_prefix(prefix) {
+            batch.delete(key);
         }
     }
 
-    Ok(())
+    fn write(&self, batch: Self::Batch) -> Result<(), StoreError> {
+        self.db.write(WriteOptions::new(), &batch)?;
+        Ok(())
+    }
 }
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::kv;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn failed_row_write_keeps_previous_data() {
+        let dir = TempDir::new().expect("temp dir");
+        let db = LevelDB::open(&dir.path().to_string_lossy()).expect("open");
+        kv::tests::assert_fault_keeps_previous(db).await;
+    }
+}
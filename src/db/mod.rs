@@ -1,5 +1,241 @@
+#[cfg(feature = "backend-leveldb")]
 pub mod leveldb;
+#[cfg(feature = "backend-rocksdb")]
 pub mod rocksdb;
+#[cfg(feature = "backend-surrealdb")]
 pub mod surrealdb;
+#[cfg(feature = "backend-postgres")]
 pub mod psql;
-pub mod mongodb;
\ No newline at end of file
+#[cfg(feature = "backend-mongodb")]
+pub mod mongodb;
+pub mod layout;
+pub mod registry;
+#[cfg(any(feature = "backend-leveldb", feature = "backend-rocksdb"))]
+mod kv;
+
+use crate::config::Config;
+use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
+use crate::models::timing::Op;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+
+/// Errors returned by every `RunePoolStore` implementation.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("meta not found")]
+    MetaNotFound,
+    #[error("serialization failed: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("{codec} encoding failed: {message}")]
+    Encoding { codec: &'static str, message: String },
+    #[error("backend `{0}` is unknown or not compiled into this build")]
+    UnknownBackend(String),
+    #[error("backend `{backend}` has no write strategy `{strategy}`")]
+    UnknownStrategy { backend: String, strategy: String },
+    #[error("{0}")]
+    Backend(Box<dyn Error + Send + Sync>),
+}
+
+impl StoreError {
+    pub fn backend<E: Into<Box<dyn Error + Send + Sync>>>(err: E) -> Self {
+        StoreError::Backend(err.into())
+    }
+}
+
+/// What a backend is and what it can do, so handlers can adapt without matching on names.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct Capabilities {
+    /// Runs in-process against a local directory rather than over the network.
+    pub embedded: bool,
+    /// Supports multi-statement transactions.
+    pub transactions: bool,
+}
+
+/// How a write treats data that is already stored, identical across backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteSemantics {
+    /// The stored dataset becomes exactly the written one.
+    #[default]
+    Replace,
+    /// Intervals are inserted or overwritten by `start_time`; others are kept.
+    #[serde(alias = "append")]
+    Upsert,
+}
+
+impl WriteSemantics {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteSemantics::Replace => "replace",
+            WriteSemantics::Upsert => "upsert",
+        }
+    }
+}
+
+/// Selects the intervals with `from <= start_time < to`, in `start_time` order, then
+/// skips `offset` of them and returns at most `limit`. Unset bounds are open.
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize, serde::Serialize)]
+pub struct IntervalRange {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+impl IntervalRange {
+    /// True if the range selects every interval.
+    pub fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && self.limit.is_none() && self.offset == 0
+    }
+
+    /// `from` and `to` as the `i64` bounds the databases compare `start_time` with. Unset
+    /// bounds are open and bounds past `i64::MAX` saturate, so they cannot wrap negative.
+    pub fn i64_bounds(&self) -> (i64, i64) {
+        let bound = |value: u64| i64::try_from(value).unwrap_or(i64::MAX);
+        (self.from.map_or(0, bound), self.to.map_or(i64::MAX, bound))
+    }
+}
+
+/// Storage operations every benchmarked database has to provide.
+#[async_trait]
+pub trait RunePoolStore: Send + Sync {
+    /// Short identifier used in query parameters and timing maps (e.g. `leveldb`).
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Configured options that affect how `op` performs, reported with its timings.
+    fn settings(&self, _op: Op) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+
+    /// Size of `response` in the backend's own value encoding, for backends that encode
+    /// values themselves rather than handing fields to the database.
+    fn encoded_len(&self, _response: &DbRunePoolResponse) -> Option<usize> {
+        None
+    }
+
+    /// Creates the tables or collections the store needs, then creates the `start_time`
+    /// index if `indexes` is set or drops it otherwise. Safe to run repeatedly.
+    async fn init_schema(&self, indexes: bool) -> Result<(), StoreError>;
+
+    /// Replaces the stored data with `response`: intervals not in `response` are removed.
+    async fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError>;
+
+    /// Inserts `response.intervals`, overwriting stored intervals with the same
+    /// `start_time` and keeping the rest. The stored meta is replaced by `response.meta`.
+    async fn upsert_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError>;
+
+    /// Names accepted by `write_with_strategy`; empty if the backend has a single write path.
+    fn write_strategies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Strategy `update_rune_pool` and `upsert_rune_pool` use, if the backend has several.
+    fn default_write_strategy(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Writes `response` with one of `write_strategies`.
+    async fn write_with_strategy(
+        &self,
+        _response: &DbRunePoolResponse,
+        _semantics: WriteSemantics,
+        strategy: &str,
+    ) -> Result<(), StoreError> {
+        Err(StoreError::UnknownStrategy {
+            backend: self.name().to_string(),
+            strategy: strategy.to_string(),
+        })
+    }
+
+    /// Writes `response` with the given semantics, using `strategy` if given and the
+    /// backend's default otherwise.
+    async fn write_rune_pool(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: Option<&str>,
+    ) -> Result<(), StoreError> {
+        match (strategy, semantics) {
+            (Some(strategy), _) => self.write_with_strategy(response, semantics, strategy).await,
+            (None, WriteSemantics::Replace) => self.update_rune_pool(response).await,
+            (None, WriteSemantics::Upsert) => self.upsert_rune_pool(response).await,
+        }
+    }
+
+    /// Reads back the stored data, intervals ordered by `start_time`.
+    async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, StoreError>;
+
+    /// Reads the stored meta and only the intervals selected by `range`, using the
+    /// backend's own filtering rather than a full read.
+    async fn get_range(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError>;
+
+    /// Reads only the stored meta. The default reads everything; backends override it
+    /// with a single-record read where their layout has one.
+    async fn get_meta(&self) -> Result<DbMeta, StoreError> {
+        Ok(self.get_rune_pool().await?.meta)
+    }
+
+    /// Reads only the interval with the latest `start_time`, or `None` if there are none.
+    /// The default reads everything; backends override it where their layout can seek to it.
+    async fn get_last_interval(&self) -> Result<Option<DbInterval>, StoreError> {
+        Ok(self.get_rune_pool().await?.intervals.pop())
+    }
+
+    /// Removes all stored data.
+    async fn clear(&self) -> Result<(), StoreError>;
+}
+
+/// Names of the backends compiled into this build, in benchmark order.
+pub const COMPILED_BACKENDS: &[&str] = &[
+    #[cfg(feature = "backend-leveldb")]
+    "leveldb",
+    #[cfg(feature = "backend-rocksdb")]
+    "rocksdb",
+    #[cfg(feature = "backend-surrealdb")]
+    "surrealdb",
+    #[cfg(feature = "backend-postgres")]
+    "psql",
+    #[cfg(feature = "backend-mongodb")]
+    "mongodb",
+];
+
+/// Opens the backend registered under `name` and initializes its schema.
+pub async fn connect(name: &str, config: &Config) -> Result<Arc<dyn RunePoolStore>, StoreError> {
+    let store = open(name, config).await?;
+    store.init_schema(config.schema_indexes).await?;
+    Ok(store)
+}
+
+async fn open(
+    name: &str,
+    #[cfg_attr(
+        not(any(
+            feature = "backend-leveldb",
+            feature = "backend-rocksdb",
+            feature = "backend-surrealdb",
+            feature = "backend-postgres",
+            feature = "backend-mongodb"
+        )),
+        allow(unused_variables)
+    )]
+    config: &Config,
+) -> Result<Arc<dyn RunePoolStore>, StoreError> {
+    match name {
+        #[cfg(feature = "backend-leveldb")]
+        "leveldb" => Ok(Arc::new(leveldb::LevelDBClient::new(config)?)),
+        #[cfg(feature = "backend-rocksdb")]
+        "rocksdb" => Ok(Arc::new(rocksdb::RocksDBClient::new(config)?)),
+        #[cfg(feature = "backend-surrealdb")]
+        "surrealdb" => Ok(Arc::new(surrealdb::SurrealDBClient::new(config).await?)),
+        #[cfg(feature = "backend-postgres")]
+        "psql" => Ok(Arc::new(psql::PsqlClient::new(config).await?)),
+        #[cfg(feature = "backend-mongodb")]
+        "mongodb" => Ok(Arc::new(mongodb::MongoDBClient::new(config).await?)),
+        _ => Err(StoreError::UnknownBackend(name.to_string())),
+    }
+}
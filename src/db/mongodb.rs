@@ -1,60 +1,467 @@
 use crate::config::Config;
+use crate::db::layout::{self, DbChunk, Layout, MergeLock};
+use crate::db::{Capabilities, IntervalRange, RunePoolStore, StoreError, WriteSemantics};
 use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
-use mongodb::{bson::doc, Client, Collection};
-use std::error::Error;
+use crate::models::timing::Op;
+use async_trait::async_trait;
+use mongodb::bson::Document;
+use mongodb::options::{Acknowledgment, CollectionOptions, IndexOptions, UpdateOneModel, WriteConcern};
+use mongodb::{bson::doc, Client, ClientSession, Collection, IndexModel};
+use std::collections::BTreeMap;
+
+/// How intervals are written to MongoDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStrategy {
+    /// `insert_many` with `ordered: true`: the server stops at the first failed document.
+    Ordered,
+    /// `insert_many` with `ordered: false`: the server may apply documents in any order.
+    Unordered,
+    /// One client-level `bulk_write` of upserts keyed by `start_time`. Needs MongoDB 8.0+.
+    BulkUpsert,
+}
+
+impl WriteStrategy {
+    pub const NAMES: &'static [&'static str] = &["ordered", "unordered", "bulk_upsert"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteStrategy::Ordered => "ordered",
+            WriteStrategy::Unordered => "unordered",
+            WriteStrategy::BulkUpsert => "bulk_upsert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ordered" => Some(WriteStrategy::Ordered),
+            "unordered" => Some(WriteStrategy::Unordered),
+            "bulk_upsert" => Some(WriteStrategy::BulkUpsert),
+            _ => None,
+        }
+    }
+}
 
 pub struct MongoDBClient {
+    client: Client,
     meta_coll: Collection<DbMeta>,
     intervals_coll: Collection<DbInterval>,
+    blob_coll: Collection<DbRunePoolResponse>,
+    chunks_coll: Collection<DbChunk>,
+    layout: Layout,
+    merge_lock: MergeLock,
+    write_concern: WriteConcern,
+    strategy: WriteStrategy,
+    /// Whether the deployment supports multi-document transactions (a replica set or
+    /// sharded cluster); on a standalone server writes are not atomic.
+    transactions: bool,
+}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(err: mongodb::error::Error) -> Self {
+        StoreError::backend(err)
+    }
+}
+
+/// Name of the unique `start_time` index on `intervals`.
+const START_TIME_INDEX: &str = "start_time_unique";
+
+/// Reads are sorted by the server on the indexed `start_time`.
+fn start_time_order() -> Document {
+    doc! { "start_time": 1 }
+}
+
+/// Reads leave out `_id`, which the models do not have.
+fn without_id() -> Document {
+    doc! { "_id": 0 }
 }
 
 impl MongoDBClient{
-    pub async fn new(config : &Config) -> Result<Self, Box<dyn Error>>{
+    pub async fn new(config : &Config) -> Result<Self, StoreError>{
+        let strategy = WriteStrategy::from_name(&config.mongodb_strategy).ok_or_else(|| {
+            StoreError::UnknownStrategy {
+                backend: "mongodb".to_string(),
+                strategy: config.mongodb_strategy.clone(),
+            }
+        })?;
+        let w = match config.mongodb_write_concern.parse::<u32>() {
+            Ok(nodes) => Acknowledgment::Nodes(nodes),
+            Err(_) => Acknowledgment::from(config.mongodb_write_concern.as_str()),
+        };
+        let write_concern = WriteConcern::builder().w(w).journal(config.mongodb_journal).build();
+        let layout = Layout::from_config(config)?;
+
         let client = Client::with_uri_str(&config.mongodb_uri).await?;
         let db   = client.database(&config.db_name);
-        let meta_coll = db.collection::<DbMeta>("meta");
-        let intervals_coll = db.collection::<DbInterval>("intervals");
+        // The driver connects lazily; `hello` makes an unreachable server fail here and
+        // tells whether it is a replica set (`setName`) or a mongos router
+        let hello = db.run_command(doc! { "hello": 1 }).await?;
+        let transactions = hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid");
+        let options = CollectionOptions::builder().write_concern(write_concern.clone()).build();
+        let meta_coll = db.collection_with_options::<DbMeta>("meta", options.clone());
+        let intervals_coll = db.collection_with_options::<DbInterval>("intervals", options.clone());
+        let blob_coll = db.collection_with_options::<DbRunePoolResponse>("rune_pool_blob", options.clone());
+        let chunks_coll = db.collection_with_options::<DbChunk>("interval_chunks", options);
         Ok(MongoDBClient {
+            client,
             meta_coll,
             intervals_coll,
+            blob_coll,
+            chunks_coll,
+            layout,
+            merge_lock: MergeLock::default(),
+            write_concern,
+            strategy,
+            transactions,
         })
     }
 
-    pub async fn update_rune_pool(&self , response : &DbRunePoolResponse)->Result<(),Box<dyn Error>>{
-        // Clear existing data
-        self.meta_coll.delete_many(doc! {}).await?;
-        self.intervals_coll.delete_many(doc! {}).await?;
+    /// Starts a session, inside a transaction when the deployment supports them, so a
+    /// failed write leaves the previous data in place.
+    async fn start_write(&self) -> Result<ClientSession, StoreError> {
+        let mut session = self.client.start_session().await?;
+        if self.transactions {
+            // Operations in a transaction take the transaction's write concern
+            session
+                .start_transaction()
+                .write_concern(self.write_concern.clone())
+                .await?;
+        }
+        Ok(session)
+    }
+
+    /// Commits a session from `start_write`. An error before this drops the session,
+    /// which aborts the transaction.
+    async fn finish_write(&self, mut session: ClientSession) -> Result<(), StoreError> {
+        if self.transactions {
+            session.commit_transaction().await?;
+        }
+        Ok(())
+    }
 
-        // Insert meta
-        self.meta_coll.insert_one(&response.meta).await?;
+    async fn write(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: WriteStrategy,
+    ) -> Result<(), StoreError> {
+        match self.layout {
+            Layout::Row => self.write_rows(response, semantics, strategy).await,
+            Layout::Blob => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_blob(&layout::target(self, response, semantics).await?).await
+            }
+            Layout::Chunked(size) => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_chunks(&layout::target(self, response, semantics).await?, size).await
+            }
+        }
+    }
+
+    async fn write_rows(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: WriteStrategy,
+    ) -> Result<(), StoreError> {
+        let mut session = self.start_write().await?;
+
+        // Clear existing data; an upsert drops only the intervals being overwritten,
+        // and a bulk upsert overwrites them in place
+        self.meta_coll.delete_many(doc! {}).session(&mut session).await?;
+        match (semantics, strategy) {
+            (WriteSemantics::Replace, _) => {
+                self.intervals_coll.delete_many(doc! {}).session(&mut session).await?;
+            }
+            (WriteSemantics::Upsert, WriteStrategy::BulkUpsert) => {}
+            (WriteSemantics::Upsert, _) => {
+                let start_times: Vec<i64> = response.intervals.iter().map(|i| i.start_time as i64).collect();
+                self.intervals_coll
+                    .delete_many(doc! { "start_time": { "$in": start_times } })
+                    .session(&mut session)
+                    .await?;
+            }
+        }
+
+        self.meta_coll.insert_one(&response.meta).session(&mut session).await?;
+        // insert_many and bulk_write reject an empty batch
+        if !response.intervals.is_empty() {
+            match strategy {
+                WriteStrategy::Ordered | WriteStrategy::Unordered => {
+                    self.intervals_coll
+                        .insert_many(&response.intervals)
+                        .ordered(strategy == WriteStrategy::Ordered)
+                        .session(&mut session)
+                        .await?;
+                }
+                WriteStrategy::BulkUpsert => {
+                    let namespace = self.intervals_coll.namespace();
+                    let models = response.intervals.iter().map(|interval| {
+                        UpdateOneModel::builder()
+                            .namespace(namespace.clone())
+                            .filter(doc! { "start_time": interval.start_time as i64 })
+                            .update(doc! {
+                                "$set": {
+                                    "end_time": interval.end_time as i64,
+                                    "count": interval.count as i64,
+                                    "units": interval.units as i64,
+                                }
+                            })
+                            .upsert(true)
+                            .build()
+                    });
+                    let mut bulk = self.client.bulk_write(models).ordered(false);
+                    if !self.transactions {
+                        // Client-level writes do not inherit the collection's write concern
+                        bulk = bulk.write_concern(self.write_concern.clone());
+                    }
+                    bulk.session(&mut session).await?;
+                }
+            }
+        }
 
-        //Insert intervals
-        self.intervals_coll.insert_many(response.intervals.clone())
-        .await?;
-    
+        self.finish_write(session).await
+    }
+
+    /// Stores `data` as the single document of `rune_pool_blob`, which caps it at
+    /// MongoDB's 16 MB document limit.
+    async fn write_blob(&self, data: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.blob_coll.replace_one(doc! {}, data).upsert(true).await?;
         Ok(())
     }
 
-    pub async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, Box<dyn Error>>{
-        let meta = self.meta_coll.find_one(doc!{}).await?.ok_or("Meta not found")?;
+    /// Replaces the meta document and stores the intervals of `data` as documents of `size`.
+    async fn write_chunks(&self, data: &DbRunePoolResponse, size: usize) -> Result<(), StoreError> {
+        let mut session = self.start_write().await?;
+        self.meta_coll.delete_many(doc! {}).session(&mut session).await?;
+        self.chunks_coll.delete_many(doc! {}).session(&mut session).await?;
+        self.meta_coll.insert_one(&data.meta).session(&mut session).await?;
+        let chunks = layout::chunks(&data.intervals, size);
+        if !chunks.is_empty() {
+            self.chunks_coll.insert_many(&chunks).session(&mut session).await?;
+        }
+        self.finish_write(session).await
+    }
+
+    async fn read_blob(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let data = self.blob_coll
+            .find_one(doc! {})
+            .projection(without_id())
+            .await?
+            .ok_or(StoreError::MetaNotFound)?;
+        Ok(DbRunePoolResponse {
+            meta: data.meta,
+            intervals: layout::select(data.intervals, range),
+        })
+    }
+
+    async fn read_chunks(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let meta = self.read_meta().await?;
+        let (from, to) = range.i64_bounds();
+        let filter = doc! {
+            "last_start_time": { "$gte": from },
+            "first_start_time": { "$lt": to },
+        };
+        let mut chunks_cursor = self.chunks_coll
+            .find(filter)
+            .sort(doc! { "first_start_time": 1 })
+            .projection(without_id())
+            .await?;
 
-        let mut intervals_cursor = self.intervals_coll.find(doc! {})
-        .await?;
-        
         let mut intervals = Vec::new();
+        while chunks_cursor.advance().await? {
+            let chunk: DbChunk = chunks_cursor.deserialize_current()?;
+            intervals.extend(chunk.intervals);
+        }
+        Ok(DbRunePoolResponse {
+            meta,
+            intervals: layout::select(intervals, range),
+        })
+    }
+
+    async fn read_meta(&self) -> Result<DbMeta, StoreError> {
+        self.meta_coll
+            .find_one(doc! {})
+            .projection(without_id())
+            .await?
+            .ok_or(StoreError::MetaNotFound)
+    }
 
+    async fn read_intervals(&self, filter: Document, range: &IntervalRange) -> Result<Vec<DbInterval>, StoreError> {
+        let mut find = self.intervals_coll
+            .find(filter)
+            .sort(start_time_order())
+            .projection(without_id())
+            .skip(range.offset as u64);
+        if let Some(limit) = range.limit {
+            find = find.limit(limit as i64);
+        }
+        let mut intervals_cursor = find.await?;
+
+        let mut intervals = Vec::new();
         while intervals_cursor.advance().await?{
             intervals.push(intervals_cursor.deserialize_current()?);
         }
+        Ok(intervals)
+    }
+}
 
-        intervals.sort_by_key(|i| i.start_time);
+#[async_trait]
+impl RunePoolStore for MongoDBClient {
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            embedded: false,
+            transactions: self.transactions,
+        }
+    }
+
+    fn settings(&self, op: Op) -> BTreeMap<String, String> {
+        let mut settings = match op {
+            Op::Write | Op::Clear => {
+                let w = match &self.write_concern.w {
+                    Some(Acknowledgment::Nodes(nodes)) => nodes.to_string(),
+                    Some(Acknowledgment::Majority) => "majority".to_string(),
+                    Some(Acknowledgment::Custom(name)) => name.clone(),
+                    _ => "default".to_string(),
+                };
+                let journal = self.write_concern.journal.unwrap_or(false);
+                BTreeMap::from([
+                    ("write_concern".to_string(), w),
+                    ("journal".to_string(), journal.to_string()),
+                    ("transaction".to_string(), (op == Op::Write && self.transactions).to_string()),
+                ])
+            }
+            Op::Read => BTreeMap::from([
+                ("sort".to_string(), "server:start_time".to_string()),
+                ("projection".to_string(), "-_id".to_string()),
+            ]),
+            Op::Schema => BTreeMap::new(),
+        };
+        settings.extend(self.layout.settings(op));
+        settings
+    }
+
+    async fn init_schema(&self, indexes: bool) -> Result<(), StoreError> {
+        // Collections are created on first insert; only the index needs managing
+        if indexes {
+            let index = IndexModel::builder()
+                .keys(doc! { "start_time": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name(START_TIME_INDEX.to_string())
+                        .unique(true)
+                        .build(),
+                )
+                .build();
+            self.intervals_coll.create_index(index).await?;
+        } else {
+            // drop_index fails on a missing index or collection, so check first
+            let names = self.intervals_coll.list_index_names().await.unwrap_or_default();
+            if names.iter().any(|name| name == START_TIME_INDEX) {
+                self.intervals_coll.drop_index(START_TIME_INDEX).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_rune_pool(&self , response : &DbRunePoolResponse)->Result<(),StoreError>{
+        self.write(response, WriteSemantics::Replace, self.strategy).await
+    }
+
+    async fn upsert_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.write(response, WriteSemantics::Upsert, self.strategy).await
+    }
+
+    /// Strategies only apply to the row layout.
+    fn write_strategies(&self) -> &'static [&'static str] {
+        match self.layout {
+            Layout::Row => WriteStrategy::NAMES,
+            Layout::Blob | Layout::Chunked(_) => &[],
+        }
+    }
+
+    fn default_write_strategy(&self) -> Option<&'static str> {
+        (self.layout == Layout::Row).then(|| self.strategy.as_str())
+    }
+
+    async fn write_with_strategy(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: &str,
+    ) -> Result<(), StoreError> {
+        let strategy = WriteStrategy::from_name(strategy).ok_or_else(|| StoreError::UnknownStrategy {
+            backend: self.name().to_string(),
+            strategy: strategy.to_string(),
+        })?;
+        self.write(response, semantics, strategy).await
+    }
+
+    async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, StoreError>{
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => return self.read_blob(&IntervalRange::default()).await,
+            Layout::Chunked(_) => return self.read_chunks(&IntervalRange::default()).await,
+        }
+        let meta = self.read_meta().await?;
+        let intervals = self.read_intervals(doc! {}, &IntervalRange::default()).await?;
+        Ok(DbRunePoolResponse { meta, intervals })
+    }
+
+    async fn get_range(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => return self.read_blob(range).await,
+            Layout::Chunked(_) => return self.read_chunks(range).await,
+        }
+        let meta = self.read_meta().await?;
+        let (from, to) = range.i64_bounds();
+        let filter = doc! {
+            "start_time": {
+                "$gte": from,
+                "$lt": to,
+            }
+        };
+        let intervals = self.read_intervals(filter, range).await?;
         Ok(DbRunePoolResponse { meta, intervals })
     }
 
-    pub async fn clear(&self) -> Result<(), Box<dyn Error>> {
-        self.meta_coll.delete_many(doc! {}).await?;
-        self.intervals_coll.delete_many(doc! {}).await?;
+    async fn get_meta(&self) -> Result<DbMeta, StoreError> {
+        match self.layout {
+            Layout::Row | Layout::Chunked(_) => self.read_meta().await,
+            Layout::Blob => Ok(self.read_blob(&IntervalRange::default()).await?.meta),
+        }
+    }
+
+    async fn get_last_interval(&self) -> Result<Option<DbInterval>, StoreError> {
+        if self.layout != Layout::Row {
+            return Ok(self.get_rune_pool().await?.intervals.pop());
+        }
+        let last = self.intervals_coll
+            .find_one(doc! {})
+            .sort(doc! { "start_time": -1 })
+            .projection(without_id())
+            .await?;
+        Ok(last)
+    }
+
+    async fn clear(&self) -> Result<(), StoreError> {
+        match self.layout {
+            Layout::Row => {
+                self.meta_coll.delete_many(doc! {}).await?;
+                self.intervals_coll.delete_many(doc! {}).await?;
+            }
+            Layout::Blob => {
+                self.blob_coll.delete_many(doc! {}).await?;
+            }
+            Layout::Chunked(_) => {
+                self.meta_coll.delete_many(doc! {}).await?;
+                self.chunks_coll.delete_many(doc! {}).await?;
+            }
+        }
         Ok(())
     }
 }
\ No newline at end of file
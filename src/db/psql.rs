@@ -1,19 +1,360 @@
 use crate::config::Config;
+use crate::db::layout::{self, Layout, MergeLock};
+use crate::db::{Capabilities, IntervalRange, RunePoolStore, StoreError, WriteSemantics};
 use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
-use sqlx::PgPool;
-use std::error::Error;
+use crate::models::timing::Op;
+use async_trait::async_trait;
+use sqlx::postgres::{PgConnection, PgRow};
+use sqlx::{PgPool, Postgres, QueryBuilder};
 use sqlx::Row;
+use std::collections::BTreeMap;
+
+/// How intervals are written to Postgres.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStrategy {
+    /// One `INSERT` per interval, each committed on its own. Not atomic: a failure part
+    /// way leaves the rows written so far.
+    PerRow,
+    /// One `INSERT` per interval inside a single transaction.
+    Transaction,
+    /// Multi-row `INSERT ... VALUES (...), (...)` statements in a transaction.
+    MultiRow,
+    /// A single `INSERT ... SELECT * FROM UNNEST($1, $2, $3, $4)` with one array per column.
+    Unnest,
+    /// Binary `COPY ... FROM STDIN` in a transaction.
+    Copy,
+}
+
+impl WriteStrategy {
+    pub const NAMES: &'static [&'static str] = &["per_row", "transaction", "multi_row", "unnest", "copy"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteStrategy::PerRow => "per_row",
+            WriteStrategy::Transaction => "transaction",
+            WriteStrategy::MultiRow => "multi_row",
+            WriteStrategy::Unnest => "unnest",
+            WriteStrategy::Copy => "copy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "per_row" => Some(WriteStrategy::PerRow),
+            "transaction" => Some(WriteStrategy::Transaction),
+            "multi_row" => Some(WriteStrategy::MultiRow),
+            "unnest" => Some(WriteStrategy::Unnest),
+            "copy" => Some(WriteStrategy::Copy),
+            _ => None,
+        }
+    }
+}
+
+/// Postgres accepts at most 65535 bind parameters per statement, four per interval.
+const MAX_ROWS_PER_INSERT: usize = 65_535 / 4;
+
 pub struct PsqlClient {
     pool: PgPool,
+    strategy: WriteStrategy,
+    layout: Layout,
+    merge_lock: MergeLock,
+}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(err: sqlx::Error) -> Self {
+        StoreError::backend(err)
+    }
 }
 
 impl PsqlClient {
-    pub async fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+    pub async fn new(config: &Config) -> Result<Self, StoreError> {
+        let strategy = WriteStrategy::from_name(&config.psql_strategy).ok_or_else(|| {
+            StoreError::UnknownStrategy {
+                backend: "psql".to_string(),
+                strategy: config.psql_strategy.clone(),
+            }
+        })?;
+        let layout = Layout::from_config(config)?;
         let pool = PgPool::connect(&config.psql_conn).await?;
-        Ok(PsqlClient { pool })
+        Ok(PsqlClient { pool, strategy, layout, merge_lock: MergeLock::default() })
+    }
+
+    async fn write(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: WriteStrategy,
+    ) -> Result<(), StoreError> {
+        match self.layout {
+            Layout::Row => self.write_rows(response, semantics, strategy).await,
+            Layout::Blob => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_blob(&layout::target(self, response, semantics).await?).await
+            }
+            Layout::Chunked(size) => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_chunks(&layout::target(self, response, semantics).await?, size).await
+            }
+        }
+    }
+
+    async fn write_rows(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: WriteStrategy,
+    ) -> Result<(), StoreError> {
+        if strategy == WriteStrategy::PerRow {
+            let mut conn = self.pool.acquire().await?;
+            return write_with(&mut conn, response, semantics, strategy).await;
+        }
+
+        let mut tx = self.pool.begin().await?;
+        write_with(&mut tx, response, semantics, strategy).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Stores `data` as the single JSONB row of `rune_pool_blob`.
+    async fn write_blob(&self, data: &DbRunePoolResponse) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO rune_pool_blob (id, data) VALUES (1, $1::jsonb)
+             ON CONFLICT (id) DO UPDATE SET data = EXCLUDED.data"
+        )
+        .bind(serde_json::to_string(data)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Replaces the meta row and stores the intervals of `data` as JSONB arrays of `size`.
+    async fn write_chunks(&self, data: &DbRunePoolResponse, size: usize) -> Result<(), StoreError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM meta").execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM interval_chunks").execute(&mut *tx).await?;
+        insert_meta(&mut tx, &data.meta).await?;
+        for chunk in layout::chunks(&data.intervals, size) {
+            sqlx::query(
+                "INSERT INTO interval_chunks (first_start_time, last_start_time, intervals)
+                 VALUES ($1, $2, $3::jsonb)"
+            )
+            .bind(chunk.first_start_time as i64)
+            .bind(chunk.last_start_time as i64)
+            .bind(serde_json::to_string(&chunk.intervals)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn read_blob(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let row = sqlx::query("SELECT data::text AS data FROM rune_pool_blob WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(StoreError::MetaNotFound)?;
+        let data: DbRunePoolResponse = serde_json::from_str(row.get::<&str, _>("data"))?;
+        Ok(DbRunePoolResponse {
+            meta: data.meta,
+            intervals: layout::select(data.intervals, range),
+        })
+    }
+
+    async fn read_chunks(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let meta = self.read_meta().await?;
+        let (from, to) = range.i64_bounds();
+        let chunk_rows = sqlx::query(
+            "SELECT intervals::text AS intervals FROM interval_chunks
+             WHERE last_start_time >= $1 AND first_start_time < $2
+             ORDER BY first_start_time ASC"
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut intervals = Vec::new();
+        for row in &chunk_rows {
+            let chunk: Vec<DbInterval> = serde_json::from_str(row.get::<&str, _>("intervals"))?;
+            intervals.extend(chunk);
+        }
+        Ok(DbRunePoolResponse {
+            meta,
+            intervals: layout::select(intervals, range),
+        })
+    }
+
+    async fn read_meta(&self) -> Result<DbMeta, StoreError> {
+        // Fetch meta (BIGINT as i64, cast to u64)
+        let meta_row = sqlx::query(
+            "SELECT start_time, end_time, start_count, end_count, start_units, end_units 
+             FROM meta LIMIT 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(StoreError::MetaNotFound)?;
+        Ok(DbMeta {
+            start_time: meta_row.get::<i64, _>("start_time") as u64,
+            end_time: meta_row.get::<i64, _>("end_time") as u64,
+            start_count: meta_row.get::<i64, _>("start_count") as u64,
+            end_count: meta_row.get::<i64, _>("end_count") as u64,
+            start_units: meta_row.get::<i64, _>("start_units") as u64,
+            end_units: meta_row.get::<i64, _>("end_units") as u64,
+        })
+    }
+}
+
+/// Removes what `semantics` says to replace, then writes `response` on `conn` with `strategy`.
+async fn write_with(
+    conn: &mut PgConnection,
+    response: &DbRunePoolResponse,
+    semantics: WriteSemantics,
+    strategy: WriteStrategy,
+) -> Result<(), StoreError> {
+    // Clear existing data; an upsert drops only the intervals being overwritten
+    sqlx::query("DELETE FROM meta").execute(&mut *conn).await?;
+    match semantics {
+        WriteSemantics::Replace => {
+            sqlx::query("DELETE FROM intervals").execute(&mut *conn).await?;
+        }
+        WriteSemantics::Upsert => {
+            let start_times: Vec<i64> = response.intervals.iter().map(|i| i.start_time as i64).collect();
+            sqlx::query("DELETE FROM intervals WHERE start_time = ANY($1)")
+                .bind(&start_times)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    insert_meta(conn, &response.meta).await?;
+    match strategy {
+        WriteStrategy::PerRow | WriteStrategy::Transaction => insert_per_row(conn, &response.intervals).await,
+        WriteStrategy::MultiRow => insert_multi_row(conn, &response.intervals).await,
+        WriteStrategy::Unnest => insert_unnest(conn, &response.intervals).await,
+        WriteStrategy::Copy => copy_in(conn, &response.intervals).await,
+    }
+}
+
+async fn insert_meta(conn: &mut PgConnection, meta: &DbMeta) -> Result<(), StoreError> {
+    // Insert meta (cast u64 to i64)
+    sqlx::query(
+        "INSERT INTO meta (start_time, end_time, start_count, end_count, start_units, end_units) 
+         VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(meta.start_time as i64)
+    .bind(meta.end_time as i64)
+    .bind(meta.start_count as i64)
+    .bind(meta.end_count as i64)
+    .bind(meta.start_units as i64)
+    .bind(meta.end_units as i64)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn insert_per_row(conn: &mut PgConnection, intervals: &[DbInterval]) -> Result<(), StoreError> {
+    // Insert intervals (cast u64 to i64)
+    for interval in intervals {
+        sqlx::query(
+            "INSERT INTO intervals (start_time, end_time, count, units) 
+             VALUES ($1, $2, $3, $4)"
+        )
+        .bind(interval.start_time as i64)
+        .bind(interval.end_time as i64)
+        .bind(interval.count as i64)
+        .bind(interval.units as i64)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn insert_multi_row(conn: &mut PgConnection, intervals: &[DbInterval]) -> Result<(), StoreError> {
+    for chunk in intervals.chunks(MAX_ROWS_PER_INSERT) {
+        let mut query = QueryBuilder::<Postgres>::new("INSERT INTO intervals (start_time, end_time, count, units) ");
+        query.push_values(chunk, |mut row, interval| {
+            row.push_bind(interval.start_time as i64)
+                .push_bind(interval.end_time as i64)
+                .push_bind(interval.count as i64)
+                .push_bind(interval.units as i64);
+        });
+        query.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn insert_unnest(conn: &mut PgConnection, intervals: &[DbInterval]) -> Result<(), StoreError> {
+    let column = |field: fn(&DbInterval) -> u64| -> Vec<i64> { intervals.iter().map(|i| field(i) as i64).collect() };
+    sqlx::query(
+        "INSERT INTO intervals (start_time, end_time, count, units)
+         SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BIGINT[], $4::BIGINT[])"
+    )
+    .bind(column(|i| i.start_time))
+    .bind(column(|i| i.end_time))
+    .bind(column(|i| i.count))
+    .bind(column(|i| i.units))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Streams `intervals` with `COPY ... FROM STDIN` in Postgres' binary format.
+async fn copy_in(conn: &mut PgConnection, intervals: &[DbInterval]) -> Result<(), StoreError> {
+    let mut copy = conn
+        .copy_in_raw("COPY intervals (start_time, end_time, count, units) FROM STDIN WITH (FORMAT binary)")
+        .await?;
+    copy.send(copy_buffer(intervals)).await?;
+    copy.finish().await?;
+    Ok(())
+}
+
+/// Encodes `intervals` as the binary `COPY` payload for the four `intervals` columns.
+fn copy_buffer(intervals: &[DbInterval]) -> Vec<u8> {
+    // Header: signature, flags, header extension length; each row: field count, then
+    // length-prefixed big-endian BIGINTs; trailer: -1
+    let mut data = Vec::with_capacity(19 + intervals.len() * (2 + 4 * 12) + 2);
+    data.extend_from_slice(b"PGCOPY\n\xff\r\n\0");
+    data.extend_from_slice(&0i32.to_be_bytes());
+    data.extend_from_slice(&0i32.to_be_bytes());
+    for interval in intervals {
+        data.extend_from_slice(&4i16.to_be_bytes());
+        for value in [interval.start_time, interval.end_time, interval.count, interval.units] {
+            data.extend_from_slice(&8i32.to_be_bytes());
+            data.extend_from_slice(&(value as i64).to_be_bytes());
+        }
+    }
+    data.extend_from_slice(&(-1i16).to_be_bytes());
+    data
+}
+
+/// Converts an `intervals` row (BIGINT as i64) back to u64 fields.
+fn interval_from_row(row: &PgRow) -> DbInterval {
+    DbInterval {
+        start_time: row.get::<i64, _>("start_time") as u64,
+        end_time: row.get::<i64, _>("end_time") as u64,
+        count: row.get::<i64, _>("count") as u64,
+        units: row.get::<i64, _>("units") as u64,
+    }
+}
+
+#[async_trait]
+impl RunePoolStore for PsqlClient {
+    fn name(&self) -> &'static str {
+        "psql"
     }
 
-    pub async fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), Box<dyn Error>> {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            embedded: false,
+            transactions: true,
+        }
+    }
+
+    fn settings(&self, op: Op) -> BTreeMap<String, String> {
+        self.layout.settings(op)
+    }
+
+    async fn init_schema(&self, indexes: bool) -> Result<(), StoreError> {
         // Create tables if they don’t exist
         sqlx::query(
             "CREATE TABLE IF NOT EXISTS meta (
@@ -41,57 +382,80 @@ impl PsqlClient {
         .execute(&self.pool)
         .await?;
 
-        // Clear existing data
-        sqlx::query("DELETE FROM meta").execute(&self.pool).await?;
-        sqlx::query("DELETE FROM intervals").execute(&self.pool).await?;
-
-        // Insert meta (cast u64 to i64)
-        sqlx::query(
-            "INSERT INTO meta (start_time, end_time, start_count, end_count, start_units, end_units) 
-             VALUES ($1, $2, $3, $4, $5, $6)"
-        )
-        .bind(response.meta.start_time as i64)
-        .bind(response.meta.end_time as i64)
-        .bind(response.meta.start_count as i64)
-        .bind(response.meta.end_count as i64)
-        .bind(response.meta.start_units as i64)
-        .bind(response.meta.end_units as i64)
-        .execute(&self.pool)
-        .await?;
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS rune_pool_blob (id INTEGER PRIMARY KEY, data JSONB NOT NULL)")
+                    .execute(&self.pool)
+                    .await?;
+            }
+            // Chunks are few, and the primary key already orders them by start time
+            Layout::Chunked(_) => {
+                sqlx::query(
+                    "CREATE TABLE IF NOT EXISTS interval_chunks (
+                        first_start_time BIGINT PRIMARY KEY,
+                        last_start_time BIGINT NOT NULL,
+                        intervals JSONB NOT NULL
+                    )"
+                )
+                .execute(&self.pool)
+                .await?;
+            }
+        }
 
-        // Insert intervals (cast u64 to i64)
-        for interval in &response.intervals {
-            sqlx::query(
-                "INSERT INTO intervals (start_time, end_time, count, units) 
-                 VALUES ($1, $2, $3, $4)"
-            )
-            .bind(interval.start_time as i64)
-            .bind(interval.end_time as i64)
-            .bind(interval.count as i64)
-            .bind(interval.units as i64)
-            .execute(&self.pool)
-            .await?;
+        if indexes {
+            sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS intervals_start_time_idx ON intervals (start_time)")
+                .execute(&self.pool)
+                .await?;
+        } else {
+            sqlx::query("DROP INDEX IF EXISTS intervals_start_time_idx")
+                .execute(&self.pool)
+                .await?;
         }
 
         Ok(())
     }
 
-    pub async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, Box<dyn Error>> {
-        // Fetch meta (BIGINT as i64, cast to u64)
-        let meta_row = sqlx::query(
-            "SELECT start_time, end_time, start_count, end_count, start_units, end_units 
-             FROM meta LIMIT 1"
-        )
-        .fetch_one(&self.pool)
-        .await?;
-        let meta = DbMeta {
-            start_time: meta_row.get::<i64, _>("start_time") as u64,
-            end_time: meta_row.get::<i64, _>("end_time") as u64,
-            start_count: meta_row.get::<i64, _>("start_count") as u64,
-            end_count: meta_row.get::<i64, _>("end_count") as u64,
-            start_units: meta_row.get::<i64, _>("start_units") as u64,
-            end_units: meta_row.get::<i64, _>("end_units") as u64,
-        };
+    async fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.write(response, WriteSemantics::Replace, self.strategy).await
+    }
+
+    async fn upsert_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.write(response, WriteSemantics::Upsert, self.strategy).await
+    }
+
+    /// Strategies only apply to the row layout.
+    fn write_strategies(&self) -> &'static [&'static str] {
+        match self.layout {
+            Layout::Row => WriteStrategy::NAMES,
+            Layout::Blob | Layout::Chunked(_) => &[],
+        }
+    }
+
+    fn default_write_strategy(&self) -> Option<&'static str> {
+        (self.layout == Layout::Row).then(|| self.strategy.as_str())
+    }
+
+    async fn write_with_strategy(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: &str,
+    ) -> Result<(), StoreError> {
+        let strategy = WriteStrategy::from_name(strategy).ok_or_else(|| StoreError::UnknownStrategy {
+            backend: self.name().to_string(),
+            strategy: strategy.to_string(),
+        })?;
+        self.write(response, semantics, strategy).await
+    }
+
+    async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, StoreError> {
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => return self.read_blob(&IntervalRange::default()).await,
+            Layout::Chunked(_) => return self.read_chunks(&IntervalRange::default()).await,
+        }
+        let meta = self.read_meta().await?;
 
         // Fetch intervals (BIGINT as i64, cast to u64)
         let interval_rows = sqlx::query(
@@ -100,22 +464,104 @@ impl PsqlClient {
         )
         .fetch_all(&self.pool)
         .await?;
-        let intervals = interval_rows
-            .into_iter()
-            .map(|row| DbInterval {
-                start_time: row.get::<i64, _>("start_time") as u64,
-                end_time: row.get::<i64, _>("end_time") as u64,
-                count: row.get::<i64, _>("count") as u64,
-                units: row.get::<i64, _>("units") as u64,
-            })
-            .collect();
+        let intervals = interval_rows.iter().map(interval_from_row).collect();
+
+        Ok(DbRunePoolResponse { meta, intervals })
+    }
+
+    async fn get_range(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => return self.read_blob(range).await,
+            Layout::Chunked(_) => return self.read_chunks(range).await,
+        }
+        let meta = self.read_meta().await?;
+        let (from, to) = range.i64_bounds();
+
+        // A NULL limit means no limit
+        let interval_rows = sqlx::query(
+            "SELECT start_time, end_time, count, units 
+             FROM intervals WHERE start_time >= $1 AND start_time < $2
+             ORDER BY start_time ASC LIMIT $3 OFFSET $4"
+        )
+        .bind(from)
+        .bind(to)
+        .bind(range.limit.map(|limit| limit as i64))
+        .bind(range.offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        let intervals = interval_rows.iter().map(interval_from_row).collect();
 
         Ok(DbRunePoolResponse { meta, intervals })
     }
 
-    pub async fn clear(&self) -> Result<(), Box<dyn Error>> {
-        sqlx::query("DELETE FROM meta").execute(&self.pool).await?;
-        sqlx::query("DELETE FROM intervals").execute(&self.pool).await?;
+    async fn get_meta(&self) -> Result<DbMeta, StoreError> {
+        match self.layout {
+            Layout::Row | Layout::Chunked(_) => self.read_meta().await,
+            Layout::Blob => Ok(self.read_blob(&IntervalRange::default()).await?.meta),
+        }
+    }
+
+    async fn get_last_interval(&self) -> Result<Option<DbInterval>, StoreError> {
+        if self.layout != Layout::Row {
+            return Ok(self.get_rune_pool().await?.intervals.pop());
+        }
+        let row = sqlx::query(
+            "SELECT start_time, end_time, count, units
+             FROM intervals ORDER BY start_time DESC LIMIT 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(interval_from_row))
+    }
+
+    async fn clear(&self) -> Result<(), StoreError> {
+        let tables: &[&str] = match self.layout {
+            Layout::Row => &["meta", "intervals"],
+            Layout::Blob => &["rune_pool_blob"],
+            Layout::Chunked(_) => &["meta", "interval_chunks"],
+        };
+        for table in tables {
+            sqlx::query(&format!("DELETE FROM {}", table)).execute(&self.pool).await?;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_buffer_is_binary_copy_format() {
+        let intervals = [
+            DbInterval { start_time: 1, end_time: 2, count: 3, units: 4 },
+            DbInterval { start_time: 0x0102_0304_0506_0708, end_time: 0, count: 0, units: 0 },
+        ];
+        let data = copy_buffer(&intervals);
+
+        let mut expected = b"PGCOPY\n\xff\r\n\0".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0, 4]);
+        for value in 1..=4u8 {
+            expected.extend_from_slice(&[0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, value]);
+        }
+        expected.extend_from_slice(&[0, 4]);
+        expected.extend_from_slice(&[0, 0, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        for _ in 0..3 {
+            expected.extend_from_slice(&[0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        expected.extend_from_slice(&[0xff, 0xff]);
+        assert_eq!(data, expected);
+        // The capacity estimate is exact
+        assert_eq!(data.len(), data.capacity());
+    }
+
+    #[test]
+    fn copy_buffer_without_rows_is_header_and_trailer() {
+        let data = copy_buffer(&[]);
+        assert_eq!(data.len(), 19 + 2);
+        assert_eq!(&data[..11], b"PGCOPY\n\xff\r\n\0");
+        assert_eq!(&data[19..], &[0xff, 0xff]);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::config::Config;
+use crate::db::{connect, RunePoolStore, StoreError, COMPILED_BACKENDS};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Availability of a backend as reported in API responses.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BackendStatus {
+    Available,
+    Unavailable { error: String },
+}
+
+enum SlotState {
+    Available(Arc<dyn RunePoolStore>),
+    Unavailable(String),
+}
+
+/// An enabled backend that may or may not currently be connected.
+pub struct BackendSlot {
+    name: String,
+    state: RwLock<SlotState>,
+    /// Index mode last requested for the backend, applied again when it reconnects.
+    indexes: AtomicBool,
+}
+
+impl BackendSlot {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The connected store, or `None` while the backend is unavailable.
+    pub fn store(&self) -> Option<Arc<dyn RunePoolStore>> {
+        match &*self.state.read().unwrap() {
+            SlotState::Available(store) => Some(store.clone()),
+            SlotState::Unavailable(_) => None,
+        }
+    }
+
+    pub fn status(&self) -> BackendStatus {
+        match &*self.state.read().unwrap() {
+            SlotState::Available(_) => BackendStatus::Available,
+            SlotState::Unavailable(error) => BackendStatus::Unavailable {
+                error: error.clone(),
+            },
+        }
+    }
+
+    /// Whether the `start_time` index is wanted: `SCHEMA_INDEXES` until `/schema/init` asks otherwise.
+    pub fn indexes(&self) -> bool {
+        self.indexes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_indexes(&self, indexes: bool) {
+        self.indexes.store(indexes, Ordering::Relaxed);
+    }
+
+    fn set(&self, state: SlotState) {
+        *self.state.write().unwrap() = state;
+    }
+}
+
+/// Opens every backend in `config.enabled_backends`.
+///
+/// Only an unknown backend name is fatal. Backends that fail to connect are
+/// recorded as unavailable and retried in the background until they come up.
+pub async fn open_backends(config: &Config) -> Result<Vec<Arc<BackendSlot>>, StoreError> {
+    if let Some(name) = config
+        .enabled_backends
+        .iter()
+        .find(|name| !COMPILED_BACKENDS.contains(&name.as_str()))
+    {
+        return Err(StoreError::UnknownBackend(name.clone()));
+    }
+
+    let mut slots = Vec::new();
+    for name in &config.enabled_backends {
+        let state = match connect(name, config).await {
+            Ok(store) => SlotState::Available(store),
+            Err(e) => {
+                eprintln!("Backend {} unavailable: {}", name, e);
+                SlotState::Unavailable(e.to_string())
+            }
+        };
+        let retry = matches!(state, SlotState::Unavailable(_));
+        let slot = Arc::new(BackendSlot {
+            name: name.clone(),
+            state: RwLock::new(state),
+            indexes: AtomicBool::new(config.schema_indexes),
+        });
+        if retry {
+            spawn_reconnect(slot.clone(), config.clone());
+        }
+        slots.push(slot);
+    }
+    Ok(slots)
+}
+
+fn spawn_reconnect(slot: Arc<BackendSlot>, mut config: Config) {
+    tokio::spawn(async move {
+        let delay = Duration::from_secs(config.backend_retry_secs);
+        loop {
+            tokio::time::sleep(delay).await;
+            // Keep an index mode set by `/schema/init` while the backend was down
+            config.schema_indexes = slot.indexes();
+            match connect(&slot.name, &config).await {
+                Ok(store) => {
+                    println!("Backend {} is now available", slot.name);
+                    slot.set(SlotState::Available(store));
+                    break;
+                }
+                Err(e) => slot.set(SlotState::Unavailable(e.to_string())),
+            }
+        }
+    });
+}
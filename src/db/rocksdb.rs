@@ -1,67 +1,112 @@
 use crate::config::Config;
-use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
-use rocksdb::{Options, DB};
-use serde_json;
-use std::error::Error;
+use crate::db::kv::{prefix_end, Entry, KvBackend, KvStore};
+use crate::db::StoreError;
+use rocksdb::{Direction, IteratorMode, Options, ReadOptions, WriteBatch, DB};
 
-pub struct RocksDBClient {
+/// RocksDB, laid out by the shared key-value store.
+pub type RocksDBClient = KvStore<RocksDB>;
+
+impl RocksDBClient {
+    pub fn new(config: &Config) -> Result<Self, StoreError> {
+        KvStore::open(config, || RocksDB::open(&config.rocksdb_path))
+    }
+}
+
+pub struct RocksDB {
     db: DB,
 }
 
-impl RocksDBClient {
-    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+impl From<rocksdb::Error> for StoreError {
+    fn from(err: rocksdb::Error) -> Self {
+        StoreError::backend(err)
+    }
+}
+
+impl RocksDB {
+    pub fn open(path: &str) -> Result<Self, StoreError> {
         let mut opts = Options::default();
         opts.create_if_missing(true);
-        let db = DB::open(&opts, &config.rocksdb_path)?;
-        Ok(RocksDBClient { db })
+        let db = DB::open(&opts, path)?;
+        Ok(RocksDB { db })
     }
+}
 
-    pub fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), Box<dyn Error>> {
-        let meta_key = "meta".as_bytes();
-        let meta_value = serde_json::to_vec(&response.meta)?;
-        self.db.put(meta_key, meta_value)?;
+impl KvBackend for RocksDB {
+    type Batch = WriteBatch;
 
-        for (index, interval) in response.intervals.iter().enumerate() {
-            let key = format!("interval_{}", index).into_bytes();
-            let value = serde_json::to_vec(interval)?;
-            self.db.put(&key, value)?;
-        }
+    fn name(&self) -> &'static str {
+        "rocksdb"
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.db.get(key)?)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.db.put(key, value)?;
         Ok(())
     }
 
-    pub fn get_rune_pool(&self) -> Result<DbRunePoolResponse, Box<dyn Error>> {
-        let meta_key = "meta".as_bytes();
-        let meta_value = self.db.get(meta_key)?.ok_or("Meta not found")?;
-        let meta: DbMeta = serde_json::from_slice(&meta_value)?;
-
-        let mut intervals = Vec::new();
-        let mut index = 0;
-        loop {
-            let key = format!("interval_{}", index).into_bytes();
-            match self.db.get(&key)? {
-                Some(value) => {
-                    let interval: DbInterval = serde_json::from_slice(&value)?;
-                    intervals.push(interval);
-                    index += 1;
-                }
-                None => break,
+    /// The upper bound stops the iterator at `end`.
+    fn scan<F>(&self, start: &[u8], end: &[u8], mut visit: F) -> Result<(), StoreError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<bool, StoreError>,
+    {
+        let mut read_opts = ReadOptions::default();
+        read_opts.set_iterate_upper_bound(end.to_vec());
+        for entry in self.db.iterator_opt(IteratorMode::From(start, Direction::Forward), read_opts) {
+            let (key, value) = entry?;
+            if !visit(&key, &value)? {
+                break;
             }
         }
-        Ok(DbRunePoolResponse { meta, intervals })
+        Ok(())
     }
 
-    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
-        self.db.delete("meta".as_bytes())?;
-        let mut index = 0;
-        loop {
-            let key = format!("interval_{}", index).into_bytes();
-            if self.db.get(&key)?.is_some() {
-                self.db.delete(&key)?;
-                index += 1;
-            } else {
-                break;
+    fn last_at_or_before(&self, prefix: &[u8], key: &[u8]) -> Result<Option<Entry>, StoreError> {
+        let mut read_opts = ReadOptions::default();
+        read_opts.set_iterate_lower_bound(prefix.to_vec());
+        match self.db.iterator_opt(IteratorMode::From(key, Direction::Reverse), read_opts).next() {
+            Some(entry) => {
+                let (found, value) = entry?;
+                Ok(Some((found.into_vec(), value.into_vec())))
             }
+            None => Ok(None),
         }
+    }
+
+    fn batch(&self) -> Self::Batch {
+        WriteBatch::default()
+    }
+
+    fn batch_put(&self, batch: &mut Self::Batch, key: &[u8], value: &[u8]) {
+        batch.put(key, value);
+    }
+
+    fn batch_delete(&self, batch: &mut Self::Batch, key: &[u8]) {
+        batch.delete(key);
+    }
+
+    fn batch_delete_prefix(&self, batch: &mut Self::Batch, prefix: &[u8]) {
+        batch.delete_range(prefix.to_vec(), prefix_end(prefix));
+    }
+
+    fn write(&self, batch: Self::Batch) -> Result<(), StoreError> {
+        self.db.write(batch)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::kv;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn failed_row_write_keeps_previous_data() {
+        let dir = TempDir::new().expect("temp dir");
+        let db = RocksDB::open(&dir.path().to_string_lossy()).expect("open");
+        kv::tests::assert_fault_keeps_previous(db).await;
+    }
+}
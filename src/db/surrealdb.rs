@@ -1,62 +1,481 @@
 use crate::config::Config;
+use crate::db::layout::{self, DbChunk, Layout, MergeLock};
+use crate::db::{Capabilities, IntervalRange, RunePoolStore, StoreError, WriteSemantics};
 use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
-use surrealdb::engine::remote::ws::{Ws, Client};
+use crate::models::timing::Op;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use surrealdb::engine::any::{self, Any};
 use surrealdb::opt::auth::Root;
 use surrealdb::Surreal;
-use std::error::Error;
+
+/// Where SurrealDB runs: a remote server over WebSocket, or in-process on one of the
+/// embedded storage engines compiled in through the `surrealdb-*` cargo features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Ws,
+    Mem,
+    RocksDb,
+    SurrealKv,
+}
+
+impl Engine {
+    pub const NAMES: &'static [&'static str] = &["ws", "mem", "rocksdb", "surrealkv"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Engine::Ws => "ws",
+            Engine::Mem => "mem",
+            Engine::RocksDb => "rocksdb",
+            Engine::SurrealKv => "surrealkv",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ws" => Some(Engine::Ws),
+            "mem" => Some(Engine::Mem),
+            "rocksdb" => Some(Engine::RocksDb),
+            "surrealkv" => Some(Engine::SurrealKv),
+            _ => None,
+        }
+    }
+
+    pub fn is_embedded(&self) -> bool {
+        *self != Engine::Ws
+    }
+
+    /// Cargo feature that compiles the engine in, and whether this build has it.
+    fn feature(&self) -> (&'static str, bool) {
+        match self {
+            Engine::Ws => ("backend-surrealdb", true),
+            Engine::Mem => ("surrealdb-mem", cfg!(feature = "surrealdb-mem")),
+            Engine::RocksDb => ("surrealdb-rocksdb", cfg!(feature = "surrealdb-rocksdb")),
+            Engine::SurrealKv => ("surrealdb-surrealkv", cfg!(feature = "surrealdb-surrealkv")),
+        }
+    }
+
+    /// Address for `surrealdb::engine::any::connect`.
+    fn endpoint(&self, config: &Config) -> String {
+        match self {
+            // SURREALDB_URL is traditionally given without a scheme
+            Engine::Ws if config.surrealdb_url.contains("://") => config.surrealdb_url.clone(),
+            Engine::Ws => format!("ws://{}", config.surrealdb_url),
+            Engine::Mem => "mem://".to_string(),
+            Engine::RocksDb => format!("rocksdb://{}", config.surrealdb_path),
+            Engine::SurrealKv => format!("surrealkv://{}", config.surrealdb_path),
+        }
+    }
+}
+
+/// How intervals are written to SurrealDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStrategy {
+    /// One `CREATE`/`UPSERT` query per interval, each its own round trip. Not atomic: a
+    /// failure part way leaves the intervals written so far.
+    PerQuery,
+    /// Every statement in a single `BEGIN`/`COMMIT` query.
+    Transaction,
+    /// One `INSERT INTO interval [...]` with all intervals bound as an array, in a
+    /// `BEGIN`/`COMMIT` query together with the delete and the meta.
+    Insert,
+}
+
+impl WriteStrategy {
+    pub const NAMES: &'static [&'static str] = &["per_query", "transaction", "insert"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteStrategy::PerQuery => "per_query",
+            WriteStrategy::Transaction => "transaction",
+            WriteStrategy::Insert => "insert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "per_query" => Some(WriteStrategy::PerQuery),
+            "transaction" => Some(WriteStrategy::Transaction),
+            "insert" => Some(WriteStrategy::Insert),
+            _ => None,
+        }
+    }
+}
+
+/// An interval as inserted by `INSERT`: `id` makes the record `interval:{start_time}`.
+#[derive(Serialize)]
+struct IntervalRecord {
+    id: u64,
+    start_time: u64,
+    end_time: u64,
+    count: u64,
+    units: u64,
+}
 
 pub struct SurrealDBClient {
-    db: Surreal<Client>,
+    db: Surreal<Any>,
+    engine: Engine,
+    strategy: WriteStrategy,
+    layout: Layout,
+    merge_lock: MergeLock,
+}
+
+impl From<surrealdb::Error> for StoreError {
+    fn from(err: surrealdb::Error) -> Self {
+        StoreError::backend(err)
+    }
 }
 
 impl SurrealDBClient {
-    pub async fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
-        let db = Surreal::new::<Ws>(&config.surrealdb_url).await?;
-        db.signin(Root {
-            username: "root",
-            password: "root",
-        })
-        .await?;
+    pub async fn new(config: &Config) -> Result<Self, StoreError> {
+        let strategy = WriteStrategy::from_name(&config.surrealdb_strategy).ok_or_else(|| {
+            StoreError::UnknownStrategy {
+                backend: "surrealdb".to_string(),
+                strategy: config.surrealdb_strategy.clone(),
+            }
+        })?;
+        let engine = Engine::from_name(&config.surrealdb_engine).ok_or_else(|| {
+            StoreError::backend(format!(
+                "unknown SurrealDB engine `{}`, expected one of {}",
+                config.surrealdb_engine,
+                Engine::NAMES.join(", ")
+            ))
+        })?;
+        let layout = Layout::from_config(config)?;
+        let (feature, compiled) = engine.feature();
+        if !compiled {
+            return Err(StoreError::backend(format!(
+                "SurrealDB engine `{}` needs the `{}` cargo feature",
+                engine.as_str(),
+                feature
+            )));
+        }
+
+        let db = any::connect(engine.endpoint(config)).await?;
+        // Embedded engines run without authentication
+        if !engine.is_embedded() {
+            db.signin(Root {
+                username: "root",
+                password: "root",
+            })
+            .await?;
+        }
         db.use_ns("runepool_ns").use_db("runepool_db").await?;
-        Ok(SurrealDBClient { db })
+        Ok(SurrealDBClient { db, engine, strategy, layout, merge_lock: MergeLock::default() })
+    }
+
+    async fn read_meta(&self) -> Result<DbMeta, StoreError> {
+        let metas: Vec<DbMeta> = self.db
+            .query("SELECT start_time, end_time, start_count, end_count, start_units, end_units FROM meta:current")
+            .await?
+            .take(0)?;
+        metas.into_iter().next().ok_or(StoreError::MetaNotFound)
+    }
+
+    /// Writes the single meta record, `meta:current`, so there is never more than one.
+    async fn write_meta(&self, meta: &DbMeta) -> Result<(), StoreError> {
+        self.db
+            .query(META_UPSERT)
+            .bind(("start_time", meta.start_time))
+            .bind(("end_time", meta.end_time))
+            .bind(("start_count", meta.start_count))
+            .bind(("end_count", meta.end_count))
+            .bind(("start_units", meta.start_units))
+            .bind(("end_units", meta.end_units))
+            .await?
+            .check()?;
+        Ok(())
     }
 
-    pub async fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), Box<dyn Error>> {
+    /// Writes `interval` to the record keyed by its `start_time` with `statement`
+    /// (`CREATE` or `UPSERT`).
+    async fn write_interval(&self, statement: &str, interval: &DbInterval) -> Result<(), StoreError> {
+        let query = format!(
+            "{} interval:{} SET start_time = $start_time, end_time = $end_time, count = $count, units = $units",
+            statement, interval.start_time
+        );
         self.db
-            .query("CREATE meta SET start_time = $start_time, end_time = $end_time, start_count = $start_count, end_count = $end_count, start_units = $start_units, end_units = $end_units")
-            .bind(("start_time", response.meta.start_time))
-            .bind(("end_time", response.meta.end_time))
-            .bind(("start_count", response.meta.start_count))
-            .bind(("end_count", response.meta.end_count))
-            .bind(("start_units", response.meta.start_units))
-            .bind(("end_units", response.meta.end_units))
+            .query(&query)
+            .bind(("start_time", interval.start_time))
+            .bind(("end_time", interval.end_time))
+            .bind(("count", interval.count))
+            .bind(("units", interval.units))
             .await?
             .check()?;
+        Ok(())
+    }
 
-        for interval in &response.intervals {
-            let query = format!(
-                "CREATE interval:{} SET start_time = $start_time, end_time = $end_time, count = $count, units = $units",
-                interval.start_time
-            );
-            self.db
-                .query(&query)
-                .bind(("start_time", interval.start_time))
-                .bind(("end_time", interval.end_time))
-                .bind(("count", interval.count))
-                .bind(("units", interval.units))
-                .await?
-                .check()?;
+    async fn write(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: WriteStrategy,
+    ) -> Result<(), StoreError> {
+        match self.layout {
+            Layout::Row => self.write_rows(response, semantics, strategy).await,
+            Layout::Blob => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_blob(layout::target(self, response, semantics).await?).await
+            }
+            Layout::Chunked(size) => {
+                let _merging = self.merge_lock.lock().await;
+                self.write_chunks(&layout::target(self, response, semantics).await?, size).await
+            }
         }
+    }
 
+    async fn write_rows(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: WriteStrategy,
+    ) -> Result<(), StoreError> {
+        match strategy {
+            WriteStrategy::PerQuery => {
+                if semantics == WriteSemantics::Replace {
+                    self.db.query("DELETE interval").await?.check()?;
+                }
+                self.write_meta(&response.meta).await?;
+                let statement = interval_statement(semantics);
+                for interval in &response.intervals {
+                    self.write_interval(statement, interval).await?;
+                }
+            }
+            WriteStrategy::Transaction => {
+                // Values are all integers, so they are inlined rather than bound
+                let mut query = String::from("BEGIN TRANSACTION;\n");
+                if semantics == WriteSemantics::Replace {
+                    query.push_str("DELETE interval;\n");
+                }
+                let meta = &response.meta;
+                query.push_str(&format!(
+                    "UPSERT meta:current SET start_time = {}, end_time = {}, start_count = {}, end_count = {}, start_units = {}, end_units = {};\n",
+                    meta.start_time, meta.end_time, meta.start_count, meta.end_count, meta.start_units, meta.end_units
+                ));
+                let statement = interval_statement(semantics);
+                for interval in &response.intervals {
+                    query.push_str(&format!(
+                        "{} interval:{} SET start_time = {}, end_time = {}, count = {}, units = {};\n",
+                        statement, interval.start_time, interval.start_time, interval.end_time, interval.count, interval.units
+                    ));
+                }
+                query.push_str("COMMIT TRANSACTION;");
+                self.db.query(query).await?.check()?;
+            }
+            WriteStrategy::Insert => {
+                let insert = match semantics {
+                    WriteSemantics::Replace => "DELETE interval; INSERT INTO interval $intervals;",
+                    WriteSemantics::Upsert => {
+                        "INSERT INTO interval $intervals ON DUPLICATE KEY UPDATE end_time = $input.end_time, count = $input.count, units = $input.units;"
+                    }
+                };
+                let query = format!("BEGIN TRANSACTION; {}; {} COMMIT TRANSACTION;", META_UPSERT, insert);
+                let records: Vec<IntervalRecord> = response
+                    .intervals
+                    .iter()
+                    .map(|interval| IntervalRecord {
+                        id: interval.start_time,
+                        start_time: interval.start_time,
+                        end_time: interval.end_time,
+                        count: interval.count,
+                        units: interval.units,
+                    })
+                    .collect();
+                let meta = &response.meta;
+                self.db
+                    .query(query)
+                    .bind(("start_time", meta.start_time))
+                    .bind(("end_time", meta.end_time))
+                    .bind(("start_count", meta.start_count))
+                    .bind(("end_count", meta.end_count))
+                    .bind(("start_units", meta.start_units))
+                    .bind(("end_units", meta.end_units))
+                    .bind(("intervals", records))
+                    .await?
+                    .check()?;
+            }
+        }
         Ok(())
     }
 
-    pub async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, Box<dyn Error>> {
-        let metas: Vec<DbMeta> = self.db
-            .query("SELECT start_time, end_time, start_count, end_count, start_units, end_units FROM meta")
+    /// Stores `data` as the single record `rune_pool_blob:current`.
+    async fn write_blob(&self, data: DbRunePoolResponse) -> Result<(), StoreError> {
+        self.db
+            .query("UPSERT rune_pool_blob:current CONTENT $data")
+            .bind(("data", data))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Replaces the meta record and stores the intervals of `data` as records of `size`,
+    /// in one `BEGIN`/`COMMIT` query.
+    async fn write_chunks(&self, data: &DbRunePoolResponse, size: usize) -> Result<(), StoreError> {
+        let query = format!(
+            "BEGIN TRANSACTION; {}; DELETE interval_chunk; INSERT INTO interval_chunk $chunks; COMMIT TRANSACTION;",
+            META_UPSERT
+        );
+        let meta = &data.meta;
+        self.db
+            .query(query)
+            .bind(("start_time", meta.start_time))
+            .bind(("end_time", meta.end_time))
+            .bind(("start_count", meta.start_count))
+            .bind(("end_count", meta.end_count))
+            .bind(("start_units", meta.start_units))
+            .bind(("end_units", meta.end_units))
+            .bind(("chunks", layout::chunks(&data.intervals, size)))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    async fn read_blob(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let blobs: Vec<DbRunePoolResponse> = self.db
+            .query("SELECT meta, intervals FROM rune_pool_blob:current")
+            .await?
+            .take(0)?;
+        let data = blobs.into_iter().next().ok_or(StoreError::MetaNotFound)?;
+        Ok(DbRunePoolResponse {
+            meta: data.meta,
+            intervals: layout::select(data.intervals, range),
+        })
+    }
+
+    async fn read_chunks(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        let meta = self.read_meta().await?;
+        let (from, to) = range.i64_bounds();
+        let chunks: Vec<DbChunk> = self.db
+            .query(
+                "SELECT first_start_time, last_start_time, intervals FROM interval_chunk
+                 WHERE last_start_time >= $from AND first_start_time < $to
+                 ORDER BY first_start_time ASC",
+            )
+            .bind(("from", from))
+            .bind(("to", to))
             .await?
             .take(0)?;
-        let meta = metas.into_iter().next().ok_or("Meta not found")?;
+        let intervals = chunks.into_iter().flat_map(|chunk| chunk.intervals);
+        Ok(DbRunePoolResponse {
+            meta,
+            intervals: layout::select(intervals, range),
+        })
+    }
+}
+
+/// Writes the single meta record, `meta:current`, from bound `$start_time` ... `$end_units`.
+const META_UPSERT: &str = "UPSERT meta:current SET start_time = $start_time, end_time = $end_time, start_count = $start_count, end_count = $end_count, start_units = $start_units, end_units = $end_units";
+
+/// Statement that writes one interval record under the given semantics.
+fn interval_statement(semantics: WriteSemantics) -> &'static str {
+    match semantics {
+        WriteSemantics::Replace => "CREATE",
+        WriteSemantics::Upsert => "UPSERT",
+    }
+}
+
+#[async_trait]
+impl RunePoolStore for SurrealDBClient {
+    fn name(&self) -> &'static str {
+        "surrealdb"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            embedded: self.engine.is_embedded(),
+            transactions: true,
+        }
+    }
+
+    fn settings(&self, op: Op) -> BTreeMap<String, String> {
+        let mut settings = match op {
+            Op::Write | Op::Read => BTreeMap::from([("engine".to_string(), self.engine.as_str().to_string())]),
+            Op::Clear | Op::Schema => BTreeMap::new(),
+        };
+        settings.extend(self.layout.settings(op));
+        settings
+    }
+
+    async fn init_schema(&self, indexes: bool) -> Result<(), StoreError> {
+        self.db
+            .query(
+                "DEFINE TABLE IF NOT EXISTS meta SCHEMAFULL;
+                 DEFINE FIELD IF NOT EXISTS start_time ON TABLE meta TYPE int;
+                 DEFINE FIELD IF NOT EXISTS end_time ON TABLE meta TYPE int;
+                 DEFINE FIELD IF NOT EXISTS start_count ON TABLE meta TYPE int;
+                 DEFINE FIELD IF NOT EXISTS end_count ON TABLE meta TYPE int;
+                 DEFINE FIELD IF NOT EXISTS start_units ON TABLE meta TYPE int;
+                 DEFINE FIELD IF NOT EXISTS end_units ON TABLE meta TYPE int;
+                 DEFINE TABLE IF NOT EXISTS interval SCHEMAFULL;
+                 DEFINE FIELD IF NOT EXISTS start_time ON TABLE interval TYPE int;
+                 DEFINE FIELD IF NOT EXISTS end_time ON TABLE interval TYPE int;
+                 DEFINE FIELD IF NOT EXISTS count ON TABLE interval TYPE int;
+                 DEFINE FIELD IF NOT EXISTS units ON TABLE interval TYPE int;",
+            )
+            .await?
+            .check()?;
+
+        // The other layouts keep intervals in nested arrays, so their tables are schemaless
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => {
+                self.db.query("DEFINE TABLE IF NOT EXISTS rune_pool_blob SCHEMALESS").await?.check()?;
+            }
+            Layout::Chunked(_) => {
+                self.db.query("DEFINE TABLE IF NOT EXISTS interval_chunk SCHEMALESS").await?.check()?;
+            }
+        }
+
+        let index = if indexes {
+            "DEFINE INDEX IF NOT EXISTS interval_start_time ON TABLE interval FIELDS start_time UNIQUE"
+        } else {
+            "REMOVE INDEX IF EXISTS interval_start_time ON TABLE interval"
+        };
+        self.db.query(index).await?.check()?;
+        Ok(())
+    }
+
+    async fn update_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.write(response, WriteSemantics::Replace, self.strategy).await
+    }
+
+    async fn upsert_rune_pool(&self, response: &DbRunePoolResponse) -> Result<(), StoreError> {
+        self.write(response, WriteSemantics::Upsert, self.strategy).await
+    }
+
+    /// Strategies only apply to the row layout.
+    fn write_strategies(&self) -> &'static [&'static str] {
+        match self.layout {
+            Layout::Row => WriteStrategy::NAMES,
+            Layout::Blob | Layout::Chunked(_) => &[],
+        }
+    }
+
+    fn default_write_strategy(&self) -> Option<&'static str> {
+        (self.layout == Layout::Row).then(|| self.strategy.as_str())
+    }
+
+    async fn write_with_strategy(
+        &self,
+        response: &DbRunePoolResponse,
+        semantics: WriteSemantics,
+        strategy: &str,
+    ) -> Result<(), StoreError> {
+        let strategy = WriteStrategy::from_name(strategy).ok_or_else(|| StoreError::UnknownStrategy {
+            backend: self.name().to_string(),
+            strategy: strategy.to_string(),
+        })?;
+        self.write(response, semantics, strategy).await
+    }
+
+    async fn get_rune_pool(&self) -> Result<DbRunePoolResponse, StoreError> {
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => return self.read_blob(&IntervalRange::default()).await,
+            Layout::Chunked(_) => return self.read_chunks(&IntervalRange::default()).await,
+        }
+        let meta = self.read_meta().await?;
 
         let intervals: Vec<DbInterval> = self.db
             .query("SELECT start_time, end_time, count, units FROM interval ORDER BY start_time ASC")
@@ -66,9 +485,64 @@ impl SurrealDBClient {
         Ok(DbRunePoolResponse { meta, intervals })
     }
 
-    pub async fn clear(&self) -> Result<(), Box<dyn Error>> {
-        self.db.query("DELETE meta").await?.check()?;
-        self.db.query("DELETE interval").await?.check()?;
+    async fn get_range(&self, range: &IntervalRange) -> Result<DbRunePoolResponse, StoreError> {
+        match self.layout {
+            Layout::Row => {}
+            Layout::Blob => return self.read_blob(range).await,
+            Layout::Chunked(_) => return self.read_chunks(range).await,
+        }
+        let meta = self.read_meta().await?;
+        let (from, to) = range.i64_bounds();
+
+        let mut query = String::from("SELECT start_time, end_time, count, units FROM interval WHERE start_time >= $from");
+        if range.to.is_some() {
+            query.push_str(" AND start_time < $to");
+        }
+        query.push_str(" ORDER BY start_time ASC");
+        if range.limit.is_some() {
+            query.push_str(" LIMIT $limit");
+        }
+        query.push_str(" START $offset");
+
+        let intervals: Vec<DbInterval> = self.db
+            .query(query)
+            .bind(("from", from))
+            .bind(("to", to))
+            .bind(("limit", range.limit))
+            .bind(("offset", range.offset))
+            .await?
+            .take(0)?;
+
+        Ok(DbRunePoolResponse { meta, intervals })
+    }
+
+    async fn get_meta(&self) -> Result<DbMeta, StoreError> {
+        match self.layout {
+            Layout::Row | Layout::Chunked(_) => self.read_meta().await,
+            Layout::Blob => Ok(self.read_blob(&IntervalRange::default()).await?.meta),
+        }
+    }
+
+    async fn get_last_interval(&self) -> Result<Option<DbInterval>, StoreError> {
+        if self.layout != Layout::Row {
+            return Ok(self.get_rune_pool().await?.intervals.pop());
+        }
+        let mut intervals: Vec<DbInterval> = self.db
+            .query("SELECT start_time, end_time, count, units FROM interval ORDER BY start_time DESC LIMIT 1")
+            .await?
+            .take(0)?;
+        Ok(intervals.pop())
+    }
+
+    async fn clear(&self) -> Result<(), StoreError> {
+        let tables: &[&str] = match self.layout {
+            Layout::Row => &["meta", "interval"],
+            Layout::Blob => &["rune_pool_blob"],
+            Layout::Chunked(_) => &["meta", "interval_chunk"],
+        };
+        for table in tables {
+            self.db.query(format!("DELETE {}", table)).await?.check()?;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,96 @@
+use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Seed used when the caller does not supply one.
+pub const DEFAULT_SEED: u64 = 42;
+
+/// Shape of a generated dataset.
+#[derive(Debug, Clone)]
+pub struct GeneratorOptions {
+    pub intervals: usize,
+    pub seed: u64,
+    /// `start_time` of the first interval, in Unix seconds.
+    pub start_time: u64,
+    /// Length of each interval in seconds (3600 for Midgard's `hour`).
+    pub interval_secs: u64,
+}
+
+impl GeneratorOptions {
+    pub fn new(intervals: usize, seed: u64) -> Self {
+        GeneratorOptions {
+            intervals,
+            seed,
+            start_time: 1_728_802_800,
+            interval_secs: 3_600,
+        }
+    }
+}
+
+/// Generates `intervals` hourly intervals from `seed`.
+pub fn generate(intervals: usize, seed: u64) -> DbRunePoolResponse {
+    generate_with(&GeneratorOptions::new(intervals, seed))
+}
+
+/// Generates a dataset that looks like Midgard's `/v2/history/runepool` output.
+///
+/// `count` (pool members) drifts slowly and `units` follows a random walk with
+/// occasional large deposits or withdrawals. The same options always produce the
+/// same data for a given `rand` version.
+pub fn generate_with(options: &GeneratorOptions) -> DbRunePoolResponse {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut count: u64 = rng.random_range(250..450);
+    let mut units: u64 = rng.random_range(300_000_000_000_000..400_000_000_000_000);
+
+    let mut intervals = Vec::with_capacity(options.intervals);
+    for i in 0..options.intervals as u64 {
+        let start_time = options.start_time + i * options.interval_secs;
+        intervals.push(DbInterval {
+            start_time,
+            end_time: start_time + options.interval_secs,
+            count,
+            units,
+        });
+
+        // Most intervals see a handful of members join or leave
+        if rng.random_bool(0.3) {
+            count = count.saturating_add_signed(rng.random_range(-2..=3)).max(1);
+        }
+
+        // Small per-interval drift, with the odd large deposit or withdrawal
+        let change = if rng.random_bool(0.01) {
+            rng.random_range(-0.05..0.05)
+        } else {
+            rng.random_range(-0.002..0.002)
+        };
+        units = ((units as f64) * (1.0 + change)).max(1.0) as u64;
+    }
+
+    DbRunePoolResponse {
+        meta: meta_for(&intervals, options.start_time),
+        intervals,
+    }
+}
+
+/// Builds the `meta` Midgard would return for `intervals`: start/end taken from the
+/// first and last interval.
+pub fn meta_for(intervals: &[DbInterval], default_time: u64) -> DbMeta {
+    match (intervals.first(), intervals.last()) {
+        (Some(first), Some(last)) => DbMeta {
+            start_time: first.start_time,
+            end_time: last.end_time,
+            start_count: first.count,
+            end_count: last.count,
+            start_units: first.units,
+            end_units: last.units,
+        },
+        _ => DbMeta {
+            start_time: default_time,
+            end_time: default_time,
+            start_count: 0,
+            end_count: 0,
+            start_units: 0,
+            end_units: 0,
+        },
+    }
+}
@@ -0,0 +1,183 @@
+//! Scheduling for the background ingestion loop: when ticks fire, and the pause switch
+//! and last-tick summary shared with the `/ingest` endpoints.
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Start of the Midgard `interval` period containing `now`, in UTC; `None` for an
+/// interval Midgard does not have. Weeks start on Monday.
+pub fn period_start(interval: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let ts = now.timestamp();
+    let floor = |len: i64| Utc.timestamp_opt(ts - ts.rem_euclid(len), 0).single();
+    let date = now.date_naive();
+    let midnight = |date: Option<NaiveDate>| Some(date?.and_hms_opt(0, 0, 0)?.and_utc());
+    match interval {
+        "5min" => floor(5 * 60),
+        "hour" => floor(60 * 60),
+        "day" => floor(24 * 60 * 60),
+        "week" => midnight(Some(date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64))),
+        "month" => midnight(NaiveDate::from_ymd_opt(date.year(), date.month(), 1)),
+        "quarter" => midnight(NaiveDate::from_ymd_opt(date.year(), (date.month() - 1) / 3 * 3 + 1, 1)),
+        "year" => midnight(NaiveDate::from_ymd_opt(date.year(), 1, 1)),
+        _ => None,
+    }
+}
+
+/// The first tick after `now` on the grid of `every` starting at `anchor`, the start of
+/// the current period, so ticks line up with interval boundaries.
+pub fn next_tick(anchor: DateTime<Utc>, every: Duration, now: DateTime<Utc>) -> DateTime<Utc> {
+    let every = every.as_secs().max(1) as i64;
+    let elapsed = (now - anchor).num_seconds().max(0);
+    anchor + chrono::Duration::seconds((elapsed / every + 1) * every)
+}
+
+/// Outcome of one ingestion tick.
+#[derive(Debug, Clone, Serialize)]
+pub struct TickReport {
+    pub at: String,
+    pub since: Option<u64>,
+    pub appended: usize,
+    pub run_id: Option<u64>,
+    /// Write time per backend, in microseconds.
+    pub timings: HashMap<String, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// State of the ingestion loop, shared between the loop and the API.
+pub struct IngestControl {
+    every: Option<Duration>,
+    paused: AtomicBool,
+    ticks: AtomicU64,
+    last_tick: RwLock<Option<TickReport>>,
+}
+
+/// What `/ingest/pause` and `/ingest/resume` report.
+#[derive(Debug, Serialize)]
+pub struct IngestStatus {
+    pub enabled: bool,
+    pub every_secs: Option<u64>,
+    pub paused: bool,
+    pub ticks: u64,
+    pub last_tick: Option<TickReport>,
+}
+
+impl IngestControl {
+    /// Control for a loop ticking every `every`, or for no loop if `None`.
+    pub fn new(every: Option<Duration>) -> Self {
+        IngestControl {
+            every,
+            paused: AtomicBool::new(false),
+            ticks: AtomicU64::new(0),
+            last_tick: RwLock::new(None),
+        }
+    }
+
+    pub fn every(&self) -> Option<Duration> {
+        self.every
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn record(&self, report: TickReport) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        *self.last_tick.write().unwrap() = Some(report);
+    }
+
+    pub fn status(&self) -> IngestStatus {
+        IngestStatus {
+            enabled: self.every.is_some(),
+            every_secs: self.every.map(|every| every.as_secs()),
+            paused: self.is_paused(),
+            ticks: self.ticks.load(Ordering::Relaxed),
+            last_tick: self.last_tick.read().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).expect("timestamp").with_timezone(&Utc)
+    }
+
+    #[test]
+    fn period_start_floors_to_each_interval() {
+        // A Wednesday in the second quarter
+        let now = at("2024-05-15T13:47:21Z");
+        let starts = [
+            ("5min", "2024-05-15T13:45:00Z"),
+            ("hour", "2024-05-15T13:00:00Z"),
+            ("day", "2024-05-15T00:00:00Z"),
+            ("week", "2024-05-13T00:00:00Z"),
+            ("month", "2024-05-01T00:00:00Z"),
+            ("quarter", "2024-04-01T00:00:00Z"),
+            ("year", "2024-01-01T00:00:00Z"),
+        ];
+        for (interval, start) in starts {
+            assert_eq!(period_start(interval, now), Some(at(start)), "{}", interval);
+        }
+        assert_eq!(period_start("fortnight", now), None);
+    }
+
+    #[test]
+    fn period_start_keeps_a_boundary() {
+        let monday = at("2024-05-13T00:00:00Z");
+        assert_eq!(period_start("week", monday), Some(monday));
+        assert_eq!(period_start("hour", monday), Some(monday));
+        // Sunday belongs to the week that started the previous Monday
+        assert_eq!(period_start("week", at("2024-05-19T23:59:59Z")), Some(monday));
+    }
+
+    #[test]
+    fn next_tick_is_the_next_grid_point_after_now() {
+        let anchor = at("2024-05-15T13:00:00Z");
+        let every = Duration::from_secs(15 * 60);
+        assert_eq!(next_tick(anchor, every, at("2024-05-15T13:00:00Z")), at("2024-05-15T13:15:00Z"));
+        assert_eq!(next_tick(anchor, every, at("2024-05-15T13:14:59Z")), at("2024-05-15T13:15:00Z"));
+        assert_eq!(next_tick(anchor, every, at("2024-05-15T13:15:00Z")), at("2024-05-15T13:30:00Z"));
+        assert_eq!(next_tick(anchor, every, at("2024-05-15T14:07:00Z")), at("2024-05-15T14:15:00Z"));
+    }
+
+    #[test]
+    fn next_tick_handles_a_clock_before_the_anchor_and_a_zero_period() {
+        let anchor = at("2024-05-15T13:00:00Z");
+        let hour = Duration::from_secs(3600);
+        assert_eq!(next_tick(anchor, hour, at("2024-05-15T12:30:00Z")), at("2024-05-15T14:00:00Z"));
+        // A zero period still moves forward, one second at a time
+        assert_eq!(next_tick(anchor, Duration::ZERO, anchor), at("2024-05-15T13:00:01Z"));
+    }
+
+    #[test]
+    fn control_reports_pauses_and_ticks() {
+        let control = IngestControl::new(Some(Duration::from_secs(60)));
+        assert!(control.status().enabled);
+        control.set_paused(true);
+        assert!(control.status().paused);
+        control.record(TickReport {
+            at: "now".to_string(),
+            since: Some(1),
+            appended: 2,
+            run_id: None,
+            timings: HashMap::new(),
+            error: None,
+        });
+        let status = control.status();
+        assert_eq!(status.ticks, 1);
+        assert_eq!(status.every_secs, Some(60));
+        assert_eq!(status.last_tick.map(|tick| tick.appended), Some(2));
+        assert!(!IngestControl::new(None).status().enabled);
+    }
+}
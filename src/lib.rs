@@ -0,0 +1,10 @@
+pub mod api;
+pub mod bench;
+pub mod config;
+pub mod db;
+pub mod generator;
+pub mod ingest;
+pub mod midgard;
+pub mod models;
+pub mod results;
+pub mod verify;
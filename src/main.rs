@@ -1,22 +1,30 @@
-mod config;
-mod models;
-mod db;
-mod api;
-
-use api::handlers::{clear_databases, fetch_and_update_rune_pool, get_rune_pool, update_rune_pool, AppState};
 use axum::{routing::get, routing::post, routing::delete, Router};
-use config::Config;
+use performance_metrics_diff_dbs::api::handlers::{backfill_rune_pool, clear_databases, fetch_and_update_rune_pool, generate_and_update_rune_pool, get_rune_pool, get_run, init_schema, list_backends, list_runs, pause_ingest, resume_ingest, run_benchmark, run_ingest, update_rune_pool, verify_backends, AppState};
+use performance_metrics_diff_dbs::config::Config;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::load();
     let state = AppState::new(config.clone()).await?;
+    if config.ingest_every.is_some() {
+        tokio::spawn(run_ingest(state.clone()));
+    }
     
     let app = Router::new()
     .route("/update", post(update_rune_pool))
     .route("/get", get(get_rune_pool))
     .route("/fetch-and-update", post(fetch_and_update_rune_pool))
+    .route("/backfill", post(backfill_rune_pool))
+    .route("/generate-and-update", post(generate_and_update_rune_pool))
     .route("/clear", delete(clear_databases))
+    .route("/backends", get(list_backends))
+    .route("/bench", post(run_benchmark))
+    .route("/runs", get(list_runs))
+    .route("/runs/{id}", get(get_run))
+    .route("/verify", get(verify_backends))
+    .route("/schema/init", post(init_schema))
+    .route("/ingest/pause", post(pause_ingest))
+    .route("/ingest/resume", post(resume_ingest))
     .with_state(state);
 
 // Load HOST and PORT from config
@@ -0,0 +1,457 @@
+//! Client for Midgard's `/v2/history/runepool` endpoint.
+
+use crate::config::Config;
+use crate::models::rune_pool::{ApiInterval, ApiMeta, ApiRunePoolResponse, DbMeta, DbRunePoolResponse};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client as HttpClient, StatusCode};
+use std::time::Duration;
+
+/// Most intervals Midgard returns for one request.
+pub const MAX_COUNT: usize = 400;
+
+/// Longest the doubling backoff grows to between two rate-limited attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum MidgardError {
+    #[error("Failed to fetch from Midgard: {0}")]
+    Request(reqwest::Error),
+    #[error("Failed to parse Midgard response: {0}")]
+    Parse(reqwest::Error),
+    #[error("Midgard is still rate limiting after {attempts} attempts")]
+    RateLimited { attempts: u32 },
+}
+
+/// Fetches up to `MAX_COUNT` intervals of the configured `INTERVAL`, starting at `from`.
+///
+/// A `429` or `503` is retried up to `MIDGARD_MAX_RETRIES` times, after the server's
+/// `Retry-After` or else `MIDGARD_BACKOFF_MS` doubled on every retry, up to a minute.
+pub async fn fetch(http: &HttpClient, config: &Config, from: u64) -> Result<ApiRunePoolResponse, MidgardError> {
+    let url = format!(
+        "{}?interval={}&from={}&count={}",
+        config.api_url, config.interval, from, MAX_COUNT
+    );
+    let mut backoff = Duration::from_millis(config.midgard_backoff_ms);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let response = http.get(&url).send().await.map_err(MidgardError::Request)?;
+        let status = response.status();
+        if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::SERVICE_UNAVAILABLE {
+            return response
+                .error_for_status()
+                .map_err(MidgardError::Request)?
+                .json::<ApiRunePoolResponse>()
+                .await
+                .map_err(MidgardError::Parse);
+        }
+        if attempts > config.midgard_max_retries {
+            return Err(MidgardError::RateLimited { attempts });
+        }
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let delay = retry_after.unwrap_or(backoff);
+        eprintln!("Midgard returned {}, retrying in {:?}", status, delay);
+        tokio::time::sleep(delay).await;
+        backoff = next_backoff(backoff);
+    }
+}
+
+/// The backoff after `backoff`: doubled, without overflowing, and capped at `MAX_BACKOFF`.
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_BACKOFF)
+}
+
+/// What appending `page` to a store whose meta is `stored` writes: the intervals starting
+/// at or after `since`, the start of the last stored interval, under a meta that runs from
+/// the stored start to the end of the page. The last stored interval is Midgard's still
+/// open current one when it was fetched, so it is written again with its final values.
+/// Without intervals the stored meta is kept.
+pub fn append(stored: &DbMeta, since: u64, page: DbRunePoolResponse) -> DbRunePoolResponse {
+    let intervals: Vec<_> = page
+        .intervals
+        .into_iter()
+        .filter(|interval| interval.start_time >= since)
+        .collect();
+    if intervals.is_empty() {
+        return DbRunePoolResponse { meta: stored.clone(), intervals };
+    }
+
+    let meta = DbMeta {
+        start_time: stored.start_time,
+        start_count: stored.start_count,
+        start_units: stored.start_units,
+        end_time: page.meta.end_time,
+        end_count: page.meta.end_count,
+        end_units: page.meta.end_units,
+    };
+    DbRunePoolResponse { meta, intervals }
+}
+
+/// Walks `from..to` one `MAX_COUNT` window at a time, each window starting where the
+/// previous one ended.
+pub struct Pages<'a> {
+    http: &'a HttpClient,
+    config: &'a Config,
+    cursor: u64,
+    to: u64,
+    done: bool,
+}
+
+impl<'a> Pages<'a> {
+    pub fn new(http: &'a HttpClient, config: &'a Config, from: u64, to: u64) -> Self {
+        Pages { http, config, cursor: from, to, done: from >= to }
+    }
+
+    /// Where the next window starts, for resuming after an error.
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+
+    /// The next window's intervals that start before `to`, or `None` once the range is covered
+    /// or Midgard has no more intervals.
+    pub async fn next(&mut self) -> Result<Option<Vec<ApiInterval>>, MidgardError> {
+        if self.done {
+            return Ok(None);
+        }
+        let page = fetch(self.http, self.config, self.cursor).await?;
+        let full = page.intervals.len() == MAX_COUNT;
+        let intervals: Vec<_> = page
+            .intervals
+            .into_iter()
+            .filter(|interval| interval.start_time >= self.cursor && interval.start_time < self.to)
+            .collect();
+
+        match intervals.last() {
+            Some(last) => {
+                self.cursor = last.end_time;
+                self.done = !full || self.cursor >= self.to;
+                Ok(Some(intervals))
+            }
+            None => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Pages merged into one dataset, in `start_time` order. Only the first and last interval
+/// and the count are kept unless the intervals themselves are asked for, so a long backfill
+/// does not hold its whole range in memory. Intervals that start at or before the last one
+/// added, as repeated at page edges, are not counted twice.
+#[derive(Default)]
+pub struct Merged {
+    first: Option<ApiInterval>,
+    last: Option<ApiInterval>,
+    len: usize,
+    /// Every merged interval, if `keep_intervals` was set.
+    intervals: Option<Vec<ApiInterval>>,
+}
+
+impl Merged {
+    pub fn new(keep_intervals: bool) -> Self {
+        Merged {
+            intervals: keep_intervals.then(Vec::new),
+            ..Merged::default()
+        }
+    }
+
+    pub fn add(&mut self, page: &[ApiInterval]) {
+        for interval in page {
+            if self.last.as_ref().is_some_and(|last| interval.start_time <= last.start_time) {
+                continue;
+            }
+            if self.first.is_none() {
+                self.first = Some(interval.clone());
+            }
+            self.last = Some(interval.clone());
+            self.len += 1;
+            if let Some(intervals) = &mut self.intervals {
+                intervals.push(interval.clone());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The meta Midgard would return for the merged intervals: the start of the first one
+    /// and the end of the last, with their `count` and `units`.
+    pub fn meta(&self) -> Option<ApiMeta> {
+        let (first, last) = (self.first.as_ref()?, self.last.as_ref()?);
+        Some(ApiMeta {
+            start_time: first.start_time,
+            end_time: last.end_time,
+            start_count: first.count,
+            end_count: last.count,
+            start_units: first.units,
+            end_units: last.units,
+        })
+    }
+
+    /// The merged dataset, if its intervals were kept.
+    pub fn into_response(self) -> Option<ApiRunePoolResponse> {
+        let meta = self.meta()?;
+        Some(ApiRunePoolResponse {
+            meta,
+            intervals: self.intervals?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::rune_pool::DbInterval;
+    use axum::extract::{Query, State};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    const HOUR: u64 = 3600;
+
+    fn db_interval(start_time: u64) -> DbInterval {
+        DbInterval {
+            start_time,
+            end_time: start_time + HOUR,
+            count: start_time / HOUR,
+            units: start_time * 10,
+        }
+    }
+
+    /// A page of hourly intervals starting at `first` and ending before `end`.
+    fn db_page(first: u64, end: u64) -> DbRunePoolResponse {
+        let intervals: Vec<_> = (first..end).step_by(HOUR as usize).map(db_interval).collect();
+        let (head, tail) = (&intervals[0], &intervals[intervals.len() - 1]);
+        DbRunePoolResponse {
+            meta: DbMeta {
+                start_time: head.start_time,
+                end_time: tail.end_time,
+                start_count: head.count,
+                end_count: tail.count,
+                start_units: head.units,
+                end_units: tail.units,
+            },
+            intervals,
+        }
+    }
+
+    #[test]
+    fn append_rewrites_the_last_stored_interval_and_extends_meta() {
+        // Stored 0h..3h, the 2h interval still open when it was fetched
+        let stored = db_page(0, 3 * HOUR).meta;
+        let page = db_page(2 * HOUR, 5 * HOUR);
+
+        let appended = append(&stored, 2 * HOUR, page.clone());
+        let starts: Vec<_> = appended.intervals.iter().map(|i| i.start_time).collect();
+        assert_eq!(starts, [2 * HOUR, 3 * HOUR, 4 * HOUR]);
+        assert_eq!(appended.meta.start_time, stored.start_time);
+        assert_eq!(appended.meta.start_units, stored.start_units);
+        assert_eq!(appended.meta.end_time, page.meta.end_time);
+        assert_eq!(appended.meta.end_count, page.meta.end_count);
+    }
+
+    #[test]
+    fn append_drops_intervals_before_since() {
+        let stored = db_page(0, 3 * HOUR).meta;
+        let appended = append(&stored, 2 * HOUR, db_page(0, 4 * HOUR));
+        assert_eq!(appended.intervals.len(), 2);
+        assert_eq!(appended.intervals[0].start_time, 2 * HOUR);
+    }
+
+    #[test]
+    fn append_keeps_the_stored_meta_without_intervals() {
+        let stored = db_page(0, 3 * HOUR).meta;
+        let appended = append(&stored, 2 * HOUR, db_page(0, 2 * HOUR));
+        assert!(appended.intervals.is_empty());
+        assert_eq!(appended.meta.end_time, stored.end_time);
+        assert_eq!(appended.meta.end_units, stored.end_units);
+    }
+
+    fn api_interval(start_time: u64) -> ApiInterval {
+        let interval = db_interval(start_time);
+        ApiInterval {
+            start_time: interval.start_time,
+            end_time: interval.end_time,
+            count: interval.count,
+            units: interval.units,
+        }
+    }
+
+    #[test]
+    fn merged_counts_repeated_edges_once() {
+        let mut merged = Merged::new(false);
+        assert!(merged.is_empty());
+        assert!(merged.meta().is_none());
+        merged.add(&[api_interval(0), api_interval(HOUR), api_interval(2 * HOUR)]);
+        merged.add(&[api_interval(2 * HOUR), api_interval(3 * HOUR)]);
+
+        assert_eq!(merged.len(), 4);
+        let meta = merged.meta().expect("meta");
+        assert_eq!(meta.start_time, 0);
+        assert_eq!(meta.start_count, 0);
+        assert_eq!(meta.end_time, 4 * HOUR);
+        assert_eq!(meta.end_units, 3 * HOUR * 10);
+        // The intervals were not kept
+        assert!(merged.into_response().is_none());
+    }
+
+    #[test]
+    fn merged_returns_the_intervals_when_kept() {
+        let mut merged = Merged::new(true);
+        merged.add(&[api_interval(0), api_interval(HOUR)]);
+        merged.add(&[api_interval(HOUR), api_interval(2 * HOUR)]);
+        let response = merged.into_response().expect("kept intervals");
+        let starts: Vec<_> = response.intervals.iter().map(|i| i.start_time).collect();
+        assert_eq!(starts, [0, HOUR, 2 * HOUR]);
+        assert_eq!(response.meta.end_time, 3 * HOUR);
+    }
+
+    /// Midgard serving hourly intervals up to `now`, rate limiting the first `throttle` requests.
+    #[derive(Clone)]
+    struct FakeMidgard {
+        now: u64,
+        throttle: Arc<AtomicU32>,
+        requests: Arc<AtomicU32>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct HistoryQuery {
+        from: u64,
+        count: usize,
+    }
+
+    async fn history(State(fake): State<FakeMidgard>, Query(query): Query<HistoryQuery>) -> Response {
+        fake.requests.fetch_add(1, Ordering::Relaxed);
+        if fake.throttle.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+            return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, "0")]).into_response();
+        }
+        // Like Midgard, start with the interval that holds `from`
+        let first = query.from - query.from % HOUR;
+        let intervals: Vec<_> = (first..)
+            .step_by(HOUR as usize)
+            .take_while(|start| start + HOUR <= fake.now)
+            .take(query.count)
+            .map(db_interval)
+            .collect();
+        let meta = match (intervals.first(), intervals.last()) {
+            (Some(head), Some(tail)) => DbMeta {
+                start_time: head.start_time,
+                end_time: tail.end_time,
+                start_count: head.count,
+                end_count: tail.count,
+                start_units: head.units,
+                end_units: tail.units,
+            },
+            _ => DbMeta {
+                start_time: query.from,
+                end_time: query.from,
+                start_count: 0,
+                end_count: 0,
+                start_units: 0,
+                end_units: 0,
+            },
+        };
+        Json(ApiRunePoolResponse::from(DbRunePoolResponse { meta, intervals })).into_response()
+    }
+
+    /// Serves `fake` on a local port and returns a config pointing at it.
+    async fn serve(fake: FakeMidgard, max_retries: u32) -> Config {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("local addr");
+        let app = Router::new().route("/", get(history)).with_state(fake);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut config = Config::load();
+        config.api_url = format!("http://{}/", addr);
+        config.midgard_max_retries = max_retries;
+        config.midgard_backoff_ms = 1;
+        config
+    }
+
+    fn fake(now_hours: u64, throttle: u32) -> FakeMidgard {
+        FakeMidgard {
+            now: now_hours * HOUR,
+            throttle: Arc::new(AtomicU32::new(throttle)),
+            requests: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// Walks every page, returning the size of each and the merged intervals.
+    async fn walk(pages: &mut Pages<'_>) -> (Vec<usize>, Merged) {
+        let mut sizes = Vec::new();
+        let mut merged = Merged::new(true);
+        while let Some(page) = pages.next().await.expect("page") {
+            sizes.push(page.len());
+            merged.add(&page);
+        }
+        (sizes, merged)
+    }
+
+    #[tokio::test]
+    async fn pages_walk_the_range_until_midgard_runs_out() {
+        let config = serve(fake(1_000, 0), 0).await;
+        let http = HttpClient::new();
+        // Starting part way into an interval skips that interval
+        let mut pages = Pages::new(&http, &config, 10, u64::MAX);
+        let (sizes, merged) = walk(&mut pages).await;
+
+        assert_eq!(sizes, [399, 400, 200]);
+        assert_eq!(pages.cursor(), 1_000 * HOUR);
+        let response = merged.into_response().expect("intervals");
+        assert_eq!(response.intervals.len(), 999);
+        assert_eq!(response.meta.start_time, HOUR);
+        assert_eq!(response.meta.end_time, 1_000 * HOUR);
+    }
+
+    #[tokio::test]
+    async fn pages_stop_before_to() {
+        let midgard = fake(1_000, 0);
+        let requests = midgard.requests.clone();
+        let config = serve(midgard, 0).await;
+        let http = HttpClient::new();
+        let mut pages = Pages::new(&http, &config, 0, 450 * HOUR);
+        let (sizes, merged) = walk(&mut pages).await;
+
+        assert_eq!(sizes, [400, 50]);
+        assert_eq!(merged.meta().expect("meta").end_time, 450 * HOUR);
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+        assert!(Pages::new(&http, &config, 5, 5).next().await.expect("empty range").is_none());
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_retried_then_reported() {
+        let http = HttpClient::new();
+        let config = serve(fake(10, 2), 2).await;
+        let page = fetch(&http, &config, 0).await.expect("retried past two 429s");
+        assert_eq!(page.intervals.len(), 10);
+
+        let config = serve(fake(10, 5), 2).await;
+        match fetch(&http, &config, 0).await {
+            Err(MidgardError::RateLimited { attempts }) => assert_eq!(attempts, 3),
+            other => panic!("expected a rate limit error, got {:?}", other.map(|page| page.intervals.len())),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(next_backoff(Duration::from_millis(500)), Duration::from_secs(1));
+        assert_eq!(next_backoff(Duration::from_secs(40)), MAX_BACKOFF);
+        // A huge MIDGARD_BACKOFF_MS saturates instead of overflowing
+        assert_eq!(next_backoff(Duration::from_millis(u64::MAX)), MAX_BACKOFF);
+        assert_eq!(next_backoff(Duration::MAX), MAX_BACKOFF);
+    }
+}
@@ -1 +1,2 @@
 pub mod rune_pool;
+pub mod timing;
\ No newline at end of file
@@ -1,6 +1,5 @@
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use sqlx::FromRow;
 // API-facing model (matches JSON)
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -47,7 +46,8 @@ pub struct ApiRunePoolResponse {
 }
 
 // DB-facing model (matches SurrealDB schema)
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, FromRow)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "backend-postgres", derive(sqlx::FromRow))]
 pub struct DbMeta {
     pub start_time: u64,
     pub end_time: u64,
@@ -57,7 +57,8 @@ pub struct DbMeta {
     pub end_units: u64,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, FromRow)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "backend-postgres", derive(sqlx::FromRow))]
 pub struct DbInterval {
     pub start_time: u64,
     pub end_time: u64,
@@ -65,7 +66,7 @@ pub struct DbInterval {
     pub units: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct DbRunePoolResponse {
     pub meta: DbMeta,
     pub intervals: Vec<DbInterval>,
@@ -93,17 +94,23 @@ impl From<ApiRunePoolResponse> for DbRunePoolResponse {
     }
 }
 
+impl From<DbMeta> for ApiMeta {
+    fn from(meta: DbMeta) -> Self {
+        ApiMeta {
+            start_time: meta.start_time,
+            end_time: meta.end_time,
+            start_count: meta.start_count,
+            end_count: meta.end_count,
+            start_units: meta.start_units,
+            end_units: meta.end_units,
+        }
+    }
+}
+
 impl From<DbRunePoolResponse> for ApiRunePoolResponse {
     fn from(db: DbRunePoolResponse) -> Self {
         ApiRunePoolResponse {
-            meta: ApiMeta {
-                start_time: db.meta.start_time,
-                end_time: db.meta.end_time,
-                start_count: db.meta.start_count,
-                end_count: db.meta.end_count,
-                start_units: db.meta.start_units,
-                end_units: db.meta.end_units,
-            },
+            meta: db.meta.into(),
             intervals: db.intervals.into_iter().map(|i| ApiInterval {
                 start_time: i.start_time,
                 end_time: i.end_time,
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Storage operation being timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Write,
+    Read,
+    Clear,
+    Schema,
+}
+
+impl Op {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Write => "write",
+            Op::Read => "read",
+            Op::Clear => "clear",
+            Op::Schema => "schema",
+        }
+    }
+}
+
+/// One timed operation against one backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timing {
+    pub backend: String,
+    pub op: Op,
+    pub nanos: u64,
+    /// Intervals written or read; 0 for operations that do not move rows.
+    pub rows: u64,
+    /// Size of the data moved, measured as its JSON encoding.
+    pub bytes: u64,
+    /// Backend-specific write strategy, for backends that have more than one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    /// Backend options in effect for the operation, e.g. MongoDB's write concern.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub settings: BTreeMap<String, String>,
+    /// Size of the data in the backend's own value encoding, where it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoded_bytes: Option<u64>,
+}
+
+/// A `Timing` with its derived throughput, as returned by the API.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingReport {
+    #[serde(flatten)]
+    pub timing: Timing,
+    pub rows_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+impl Timing {
+    pub fn new(backend: &str, op: Op, elapsed: Duration, rows: usize, bytes: usize) -> Self {
+        Timing {
+            backend: backend.to_string(),
+            op,
+            nanos: elapsed.as_nanos() as u64,
+            rows: rows as u64,
+            bytes: bytes as u64,
+            strategy: None,
+            settings: BTreeMap::new(),
+            encoded_bytes: None,
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: Option<&str>) -> Self {
+        self.strategy = strategy.map(str::to_string);
+        self
+    }
+
+    pub fn with_settings(mut self, settings: BTreeMap<String, String>) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_encoded_bytes(mut self, encoded_bytes: Option<usize>) -> Self {
+        self.encoded_bytes = encoded_bytes.map(|bytes| bytes as u64);
+        self
+    }
+
+    pub fn micros(&self) -> u64 {
+        self.nanos / 1_000
+    }
+
+    pub fn rows_per_sec(&self) -> f64 {
+        per_sec(self.rows, self.nanos)
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_sec(self.bytes, self.nanos)
+    }
+
+    pub fn report(&self) -> TimingReport {
+        TimingReport {
+            timing: self.clone(),
+            rows_per_sec: self.rows_per_sec(),
+            bytes_per_sec: self.bytes_per_sec(),
+        }
+    }
+}
+
+fn per_sec(amount: u64, nanos: u64) -> f64 {
+    if nanos == 0 {
+        return 0.0;
+    }
+    amount as f64 * 1e9 / nanos as f64
+}
+
+/// Size of `value` as JSON, used as the `bytes` of a `Timing`.
+pub fn encoded_len<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map(|bytes| bytes.len()).unwrap_or(0)
+}
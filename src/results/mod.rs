@@ -0,0 +1,184 @@
+use crate::config::Config;
+use crate::models::timing::Timing;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Non-secret settings that affect the numbers in a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    pub interval: String,
+    pub enabled_backends: Vec<String>,
+    /// Whether the `start_time` indexes were in place; absent from runs recorded before it was tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_indexes: Option<bool>,
+}
+
+impl From<&Config> for ConfigSnapshot {
+    fn from(config: &Config) -> Self {
+        ConfigSnapshot {
+            interval: config.interval.clone(),
+            enabled_backends: config.enabled_backends.clone(),
+            schema_indexes: Some(config.schema_indexes),
+        }
+    }
+}
+
+/// One recorded call to a benchmarked endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: u64,
+    /// RFC 3339 time the run finished.
+    pub timestamp: String,
+    pub endpoint: String,
+    pub git_commit: Option<String>,
+    pub config: ConfigSnapshot,
+    /// Write mode or other per-request options, as given in the query string.
+    #[serde(default)]
+    pub params: HashMap<String, String>,
+    pub rows: u64,
+    pub bytes: u64,
+    pub timings: Vec<Timing>,
+    /// Error message per backend that failed.
+    #[serde(default)]
+    pub errors: HashMap<String, String>,
+}
+
+/// The fields a handler fills in; the store adds id, timestamp, commit and config.
+#[derive(Debug, Default)]
+pub struct NewRun {
+    pub endpoint: &'static str,
+    pub params: HashMap<String, String>,
+    pub rows: u64,
+    pub bytes: u64,
+    pub timings: Vec<Timing>,
+    pub errors: HashMap<String, String>,
+}
+
+/// Append-only JSON-lines history of benchmark runs.
+pub struct ResultsStore {
+    path: PathBuf,
+    git_commit: Option<String>,
+    config: ConfigSnapshot,
+    /// Index mode of the stores, changed by `/schema/init`.
+    schema_indexes: AtomicBool,
+    next_id: Mutex<u64>,
+}
+
+impl ResultsStore {
+    pub async fn open(config: &Config) -> io::Result<Self> {
+        let path = PathBuf::from(&config.results_path);
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).await?;
+        }
+        terminate_last_line(&path).await?;
+        let last_id = read_records(&path).await?.last().map_or(0, |record| record.id);
+
+        Ok(ResultsStore {
+            path,
+            git_commit: git_commit(),
+            config: ConfigSnapshot::from(config),
+            schema_indexes: AtomicBool::new(config.schema_indexes),
+            next_id: Mutex::new(last_id + 1),
+        })
+    }
+
+    /// Records later runs as made with (`true`) or without the `start_time` indexes.
+    pub fn set_schema_indexes(&self, indexes: bool) {
+        self.schema_indexes.store(indexes, Ordering::Relaxed);
+    }
+
+    /// Appends `run` to the history and returns its id.
+    pub async fn record(&self, run: NewRun) -> io::Result<u64> {
+        let mut next_id = self.next_id.lock().await;
+        let record = RunRecord {
+            id: *next_id,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            endpoint: run.endpoint.to_string(),
+            git_commit: self.git_commit.clone(),
+            config: ConfigSnapshot {
+                schema_indexes: Some(self.schema_indexes.load(Ordering::Relaxed)),
+                ..self.config.clone()
+            },
+            params: run.params,
+            rows: run.rows,
+            bytes: run.bytes,
+            timings: run.timings,
+            errors: run.errors,
+        };
+
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&line).await?;
+
+        *next_id += 1;
+        Ok(record.id)
+    }
+
+    /// All recorded runs, oldest first.
+    pub async fn list(&self) -> io::Result<Vec<RunRecord>> {
+        read_records(&self.path).await
+    }
+
+    pub async fn get(&self, id: u64) -> io::Result<Option<RunRecord>> {
+        Ok(self.list().await?.into_iter().find(|record| record.id == id))
+    }
+}
+
+/// The records in the history at `path`. Lines that do not parse, such as one a crash left
+/// half-written, are logged and skipped.
+async fn read_records(path: &Path) -> io::Result<Vec<RunRecord>> {
+    let contents = match fs::read(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let records = String::from_utf8_lossy(&contents)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                eprintln!("Skipping line {} of {}: {}", i + 1, path.display(), e);
+                None
+            }
+        })
+        .collect();
+    Ok(records)
+}
+
+/// Ends the history at `path` with a newline if it does not, so a record appended after a
+/// half-written line starts on its own line.
+async fn terminate_last_line(path: &Path) -> io::Result<()> {
+    let contents = match fs::read(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if contents.last().is_some_and(|&byte| byte != b'\n') {
+        OpenOptions::new().append(true).open(path).await?.write_all(b"\n").await?;
+    }
+    Ok(())
+}
+
+/// `GIT_COMMIT` if set, otherwise the checkout's current commit.
+fn git_commit() -> Option<String> {
+    if let Ok(commit) = std::env::var("GIT_COMMIT") {
+        return Some(commit);
+    }
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
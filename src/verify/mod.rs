@@ -0,0 +1,281 @@
+use crate::db::WriteSemantics;
+use crate::models::rune_pool::{DbInterval, DbMeta, DbRunePoolResponse};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// The most recent payload written through the API, kept to check the stores against.
+#[derive(Debug, Clone)]
+pub struct LastWrite {
+    pub payload: Arc<DbRunePoolResponse>,
+    pub semantics: WriteSemantics,
+}
+
+/// A single field whose stored value differs from the expected one.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldMismatch {
+    pub field: &'static str,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntervalMismatch {
+    pub start_time: u64,
+    pub fields: Vec<FieldMismatch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum MetaMismatch {
+    /// Expected a meta but the store has none.
+    Missing,
+    /// The store has a meta where none was expected.
+    Extra,
+    Fields { fields: Vec<FieldMismatch> },
+}
+
+/// Differences between an expected and a stored dataset. Intervals are matched by
+/// `start_time`; `missing`, `extra` and `differing` list those start times.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DatasetDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<MetaMismatch>,
+    pub missing: Vec<u64>,
+    pub extra: Vec<u64>,
+    pub differing: Vec<IntervalMismatch>,
+}
+
+impl DatasetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.meta.is_none() && self.missing.is_empty() && self.extra.is_empty() && self.differing.is_empty()
+    }
+}
+
+/// Outcome of checking one backend.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackendReport {
+    /// Number of stored intervals, if the read succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intervals: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Differences from the last written payload, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub against_payload: Option<DatasetDiff>,
+    /// Differences from the reference backend; absent for the reference itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub against_reference: Option<DatasetDiff>,
+}
+
+impl BackendReport {
+    fn diverges(&self) -> bool {
+        [&self.against_payload, &self.against_reference]
+            .into_iter()
+            .flatten()
+            .any(|diff| !diff.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LastWriteSummary {
+    pub semantics: WriteSemantics,
+    pub intervals: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    /// True when every backend was read and matches both the payload and the reference.
+    pub consistent: bool,
+    /// True when at least one backend's data differs; false if only reads failed.
+    pub divergent: bool,
+    /// Backend the others are compared against: the first one that could be read.
+    pub reference: Option<String>,
+    pub last_write: Option<LastWriteSummary>,
+    pub backends: HashMap<String, BackendReport>,
+}
+
+/// Compares what each backend returned with the last write and with each other.
+///
+/// `reads` holds one entry per backend in registration order; `Ok(None)` means the
+/// store is empty. After an upsert the stores may legitimately hold intervals that were
+/// not in the payload, so those are not reported as `extra` against it.
+pub fn verify(
+    reads: Vec<(String, Result<Option<DbRunePoolResponse>, String>)>,
+    last_write: Option<&LastWrite>,
+) -> VerifyReport {
+    let reference = reads
+        .iter()
+        .find_map(|(name, read)| read.as_ref().ok().map(|data| (name.clone(), data.clone())));
+
+    let mut backends = HashMap::new();
+    for (name, read) in reads {
+        let mut report = BackendReport::default();
+        match read {
+            Ok(data) => {
+                report.intervals = Some(data.as_ref().map_or(0, |data| data.intervals.len()));
+                if let Some(last_write) = last_write {
+                    let allow_extra = last_write.semantics == WriteSemantics::Upsert;
+                    report.against_payload = Some(diff(Some(&last_write.payload), data.as_ref(), allow_extra));
+                }
+                if let Some((reference_name, reference_data)) = &reference {
+                    if *reference_name != name {
+                        report.against_reference = Some(diff(reference_data.as_ref(), data.as_ref(), false));
+                    }
+                }
+            }
+            Err(e) => report.error = Some(e),
+        }
+        backends.insert(name, report);
+    }
+
+    let divergent = backends.values().any(BackendReport::diverges);
+    let consistent = !divergent && backends.values().all(|report| report.error.is_none());
+    VerifyReport {
+        consistent,
+        divergent,
+        reference: reference.map(|(name, _)| name),
+        last_write: last_write.map(|last_write| LastWriteSummary {
+            semantics: last_write.semantics,
+            intervals: last_write.payload.intervals.len(),
+        }),
+        backends,
+    }
+}
+
+/// Field-by-field differences of `actual` from `expected`; `None` is an empty store.
+pub fn diff(
+    expected: Option<&DbRunePoolResponse>,
+    actual: Option<&DbRunePoolResponse>,
+    allow_extra: bool,
+) -> DatasetDiff {
+    let mut result = DatasetDiff {
+        meta: match (expected, actual) {
+            (Some(_), None) => Some(MetaMismatch::Missing),
+            (None, Some(_)) => Some(MetaMismatch::Extra),
+            (Some(expected), Some(actual)) => {
+                let fields = meta_mismatches(&expected.meta, &actual.meta);
+                (!fields.is_empty()).then_some(MetaMismatch::Fields { fields })
+            }
+            (None, None) => None,
+        },
+        ..Default::default()
+    };
+
+    let mut expected: BTreeMap<u64, &DbInterval> = expected
+        .map(|data| data.intervals.iter().map(|interval| (interval.start_time, interval)).collect())
+        .unwrap_or_default();
+    for interval in actual.map_or(&[][..], |data| &data.intervals) {
+        match expected.remove(&interval.start_time) {
+            Some(wanted) => {
+                let fields = interval_mismatches(wanted, interval);
+                if !fields.is_empty() {
+                    result.differing.push(IntervalMismatch {
+                        start_time: interval.start_time,
+                        fields,
+                    });
+                }
+            }
+            // Also catches a second copy of an interval that was already matched
+            None if !allow_extra => result.extra.push(interval.start_time),
+            None => {}
+        }
+    }
+    result.missing = expected.into_keys().collect();
+    result
+}
+
+fn meta_mismatches(expected: &DbMeta, actual: &DbMeta) -> Vec<FieldMismatch> {
+    mismatches(&[
+        ("start_time", expected.start_time, actual.start_time),
+        ("end_time", expected.end_time, actual.end_time),
+        ("start_count", expected.start_count, actual.start_count),
+        ("end_count", expected.end_count, actual.end_count),
+        ("start_units", expected.start_units, actual.start_units),
+        ("end_units", expected.end_units, actual.end_units),
+    ])
+}
+
+fn interval_mismatches(expected: &DbInterval, actual: &DbInterval) -> Vec<FieldMismatch> {
+    mismatches(&[
+        ("end_time", expected.end_time, actual.end_time),
+        ("count", expected.count, actual.count),
+        ("units", expected.units, actual.units),
+    ])
+}
+
+fn mismatches(fields: &[(&'static str, u64, u64)]) -> Vec<FieldMismatch> {
+    fields
+        .iter()
+        .filter(|(_, expected, actual)| expected != actual)
+        .map(|&(field, expected, actual)| FieldMismatch { field, expected, actual })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::generate;
+
+    #[test]
+    fn identical_datasets_do_not_differ() {
+        let data = generate(50, 7);
+        assert!(diff(Some(&data), Some(&data.clone()), false).is_empty());
+        assert!(diff(None, None, false).is_empty());
+    }
+
+    #[test]
+    fn a_missing_or_unexpected_store_is_a_meta_mismatch() {
+        let data = generate(3, 7);
+        let missing = diff(Some(&data), None, false);
+        assert!(matches!(missing.meta, Some(MetaMismatch::Missing)));
+        assert_eq!(missing.missing.len(), 3);
+
+        let extra = diff(None, Some(&data), false);
+        assert!(matches!(extra.meta, Some(MetaMismatch::Extra)));
+        assert_eq!(extra.extra.len(), 3);
+    }
+
+    #[test]
+    fn intervals_are_matched_by_start_time() {
+        let expected = generate(5, 7);
+        let mut actual = expected.clone();
+        let dropped = actual.intervals.remove(1).start_time;
+        actual.intervals[2].units += 1;
+        let changed = actual.intervals[2].clone();
+        let mut added = changed.clone();
+        added.start_time += 1_000_000;
+        actual.intervals.push(added.clone());
+        // A second copy of a matched interval counts as extra
+        actual.intervals.push(expected.intervals[0].clone());
+        actual.meta.end_count += 2;
+
+        let diff = diff(Some(&expected), Some(&actual), false);
+        assert_eq!(diff.missing, [dropped]);
+        assert_eq!(diff.extra, [added.start_time, expected.intervals[0].start_time]);
+        assert_eq!(diff.differing.len(), 1);
+        assert_eq!(diff.differing[0].start_time, changed.start_time);
+        let fields: Vec<_> = diff.differing[0].fields.iter().map(|f| (f.field, f.expected, f.actual)).collect();
+        assert_eq!(fields, [("units", changed.units - 1, changed.units)]);
+        match diff.meta {
+            Some(MetaMismatch::Fields { fields }) => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(fields[0].field, "end_count");
+            }
+            other => panic!("expected an end_count mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn allow_extra_ignores_intervals_beyond_the_payload() {
+        let stored = generate(10, 7);
+        let mut payload = stored.clone();
+        payload.intervals.truncate(4);
+
+        let upserted = diff(Some(&payload), Some(&stored), true);
+        assert!(upserted.is_empty(), "{:?}", upserted);
+        assert_eq!(diff(Some(&payload), Some(&stored), false).extra.len(), 6);
+        // Missing intervals still count
+        assert_eq!(diff(Some(&stored), Some(&payload), true).missing.len(), 6);
+    }
+}
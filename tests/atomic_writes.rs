@@ -0,0 +1,124 @@
+//! Fault injection for replace writes: a write that fails or is abandoned part way must
+//! leave the previously stored dataset intact.
+//!
+//! Runs against the stores `common` opens; a networked store's stored data is replaced.
+
+use performance_metrics_diff_dbs::db::{self, RunePoolStore, COMPILED_BACKENDS};
+use performance_metrics_diff_dbs::generator::{generate_with, GeneratorOptions};
+use performance_metrics_diff_dbs::models::rune_pool::DbRunePoolResponse;
+use performance_metrics_diff_dbs::verify;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+/// Opens `backend` with its `start_time` index, the row layout and its transactional write
+/// strategy, whatever the environment selects. `None` if the backend is not configured or
+/// has no transactions; the key-value stores' batches are covered by their unit tests.
+async fn open_store(backend: &str) -> Option<(Arc<dyn RunePoolStore>, Option<TempDir>)> {
+    let (config, dir) = common::store_config(backend, |config| {
+        config.schema_indexes = true;
+        config.layout = "row".to_string();
+        #[cfg(feature = "backend-postgres")]
+        {
+            config.psql_strategy = "transaction".to_string();
+        }
+        #[cfg(feature = "backend-surrealdb")]
+        {
+            config.surrealdb_strategy = "transaction".to_string();
+        }
+    })?;
+
+    let store = db::connect(backend, &config)
+        .await
+        .unwrap_or_else(|e| panic!("{} is configured but failed to open: {}", backend, e));
+    if !store.capabilities().transactions {
+        // A standalone MongoDB commits the delete before the insert fails
+        eprintln!("Skipping {}: the store has no transactions", backend);
+        return None;
+    }
+    Some((store, dir))
+}
+
+/// The dataset stored before each faulty write.
+fn previous() -> DbRunePoolResponse {
+    generate_with(&GeneratorOptions::new(100, 1))
+}
+
+/// A dataset sharing no `start_time` with `previous`, so any trace of it is visible.
+fn next(intervals: usize) -> DbRunePoolResponse {
+    let mut options = GeneratorOptions::new(intervals, 2);
+    options.start_time += 1_000 * options.interval_secs;
+    generate_with(&options)
+}
+
+async fn assert_stored(store: &dyn RunePoolStore, expected: &DbRunePoolResponse) {
+    let stored = store.get_rune_pool().await.expect("read");
+    let diff = verify::diff(Some(expected), Some(&stored), false);
+    assert!(
+        diff.is_empty(),
+        "{}: stored data changed: meta {:?}, {} missing, {} extra, {} differing intervals",
+        store.name(),
+        diff.meta,
+        diff.missing.len(),
+        diff.extra.len(),
+        diff.differing.len()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_replace_keeps_previous_data() {
+    for backend in COMPILED_BACKENDS {
+        let Some((store, _dir)) = open_store(backend).await else {
+            continue;
+        };
+        let previous = previous();
+        store.update_rune_pool(&previous).await.expect("write previous");
+
+        // A repeated start_time near the end breaks the unique index after most of the
+        // payload has been written
+        let mut faulty = next(1_000);
+        let first = faulty.intervals[0].clone();
+        faulty.intervals.push(first);
+
+        let result = store.update_rune_pool(&faulty).await;
+        assert!(result.is_err(), "{}: the duplicate interval was accepted", backend);
+        assert_stored(store.as_ref(), &previous).await;
+        store.clear().await.expect("clear");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abandoned_replace_is_all_or_nothing() {
+    for backend in COMPILED_BACKENDS {
+        let Some((store, _dir)) = open_store(backend).await else {
+            continue;
+        };
+        let previous = previous();
+        store.update_rune_pool(&previous).await.expect("write previous");
+
+        // Drop the write future part way through, as a crashed handler would
+        let next = next(20_000);
+        let abandoned = tokio::time::timeout(Duration::from_millis(5), store.update_rune_pool(&next))
+            .await
+            .is_err();
+        // A server may still finish a statement it already received; give it time to settle
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let stored = store.get_rune_pool().await.expect("read");
+        let kept = verify::diff(Some(&previous), Some(&stored), false).is_empty();
+        let replaced = verify::diff(Some(&next), Some(&stored), false).is_empty();
+        assert!(
+            kept || replaced,
+            "{}: the write (abandoned: {}) left a mix of old and new data ({} intervals)",
+            backend,
+            abandoned,
+            stored.intervals.len()
+        );
+        if !abandoned {
+            assert!(replaced, "{}: a completed write did not replace the data", backend);
+        }
+        store.clear().await.expect("clear");
+    }
+}
@@ -0,0 +1,63 @@
+//! Store fixtures shared by the integration tests and the benchmarks.
+//!
+//! Embedded stores, including SurrealDB with an embedded `SURREALDB_ENGINE`, run against
+//! fresh temp directories. Networked stores only run when their connection variable
+//! (`SURREALDB_URL`, `PSQL_CONN`, `MONGODB_URI`) is set, in the environment or in `.env`.
+
+use performance_metrics_diff_dbs::config::Config;
+use tempfile::TempDir;
+
+/// Environment variable that must be set for a networked backend to be used.
+pub fn connection_var(
+    backend: &str,
+    #[cfg_attr(not(feature = "backend-surrealdb"), allow(unused_variables))] config: &Config,
+) -> Option<&'static str> {
+    match backend {
+        // The embedded SurrealDB engines need no server
+        #[cfg(feature = "backend-surrealdb")]
+        "surrealdb" if config.surrealdb_engine != "ws" => None,
+        "surrealdb" => Some("SURREALDB_URL"),
+        "psql" => Some("PSQL_CONN"),
+        "mongodb" => Some("MONGODB_URI"),
+        _ => None,
+    }
+}
+
+/// The config to open `backend` with after `configure` adjusts it, pointing embedded
+/// stores at a temp dir that must outlive the store, or `None` if the backend is not
+/// configured.
+pub fn store_config(backend: &str, configure: impl FnOnce(&mut Config)) -> Option<(Config, Option<TempDir>)> {
+    // Load `.env` before reading connection variables that may only be set there
+    dotenvy::dotenv().ok();
+    let mut config = Config::load();
+    configure(&mut config);
+    if let Some(var) = connection_var(backend, &config) {
+        if std::env::var(var).is_err() {
+            eprintln!("Skipping {}: {} is not set", backend, var);
+            return None;
+        }
+    }
+
+    let dir = match backend {
+        #[cfg(feature = "backend-leveldb")]
+        "leveldb" => {
+            let dir = TempDir::new().expect("temp dir");
+            config.leveldb_path = dir.path().to_string_lossy().into_owned();
+            Some(dir)
+        }
+        #[cfg(feature = "backend-rocksdb")]
+        "rocksdb" => {
+            let dir = TempDir::new().expect("temp dir");
+            config.rocksdb_path = dir.path().to_string_lossy().into_owned();
+            Some(dir)
+        }
+        #[cfg(feature = "backend-surrealdb")]
+        "surrealdb" if matches!(config.surrealdb_engine.as_str(), "rocksdb" | "surrealkv") => {
+            let dir = TempDir::new().expect("temp dir");
+            config.surrealdb_path = dir.path().to_string_lossy().into_owned();
+            Some(dir)
+        }
+        _ => None,
+    };
+    Some((config, dir))
+}
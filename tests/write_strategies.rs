@@ -0,0 +1,111 @@
+//! Round trips through every write strategy: each one's replace and upsert must leave
+//! exactly the expected dataset, as read back through `get_rune_pool`.
+//!
+//! Runs against the stores `common` opens; a networked store's stored data is replaced.
+
+use performance_metrics_diff_dbs::db::{self, RunePoolStore, WriteSemantics, COMPILED_BACKENDS};
+use performance_metrics_diff_dbs::generator::{generate_with, GeneratorOptions};
+use performance_metrics_diff_dbs::models::rune_pool::DbRunePoolResponse;
+use performance_metrics_diff_dbs::verify;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+mod common;
+
+/// Opens `backend` with the row layout, the only one strategies apply to, and SurrealDB on
+/// its in-memory engine when that is compiled in. `None` if the backend is not configured.
+async fn open_store(backend: &str) -> Option<(Arc<dyn RunePoolStore>, Option<TempDir>)> {
+    let (config, dir) = common::store_config(backend, |config| {
+        config.layout = "row".to_string();
+        // Needs no server, so the SurrealDB strategies always run
+        #[cfg(feature = "surrealdb-mem")]
+        {
+            config.surrealdb_engine = "mem".to_string();
+        }
+    })?;
+    let store = db::connect(backend, &config)
+        .await
+        .unwrap_or_else(|e| panic!("{} is configured but failed to open: {}", backend, e));
+    Some((store, dir))
+}
+
+fn dataset(intervals: usize, seed: u64, offset: usize) -> DbRunePoolResponse {
+    let mut options = GeneratorOptions::new(intervals, seed);
+    options.start_time += offset as u64 * options.interval_secs;
+    generate_with(&options)
+}
+
+/// `stored` after upserting `update`: intervals merged by `start_time`, `update`'s meta.
+fn upserted(stored: &DbRunePoolResponse, update: &DbRunePoolResponse) -> DbRunePoolResponse {
+    let mut intervals = BTreeMap::new();
+    for interval in stored.intervals.iter().chain(&update.intervals) {
+        intervals.insert(interval.start_time, interval.clone());
+    }
+    DbRunePoolResponse {
+        meta: update.meta.clone(),
+        intervals: intervals.into_values().collect(),
+    }
+}
+
+async fn assert_stored(store: &dyn RunePoolStore, strategy: &str, step: &str, expected: &DbRunePoolResponse) {
+    let stored = store.get_rune_pool().await.expect("read");
+    let diff = verify::diff(Some(expected), Some(&stored), false);
+    assert!(
+        diff.is_empty(),
+        "{} {}: {} left meta {:?}, {} missing, {} extra, {} differing intervals",
+        store.name(),
+        strategy,
+        step,
+        diff.meta,
+        diff.missing.len(),
+        diff.extra.len(),
+        diff.differing.len()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_strategy_round_trips() {
+    for backend in COMPILED_BACKENDS {
+        let Some((store, _dir)) = open_store(backend).await else {
+            continue;
+        };
+        for strategy in store.write_strategies() {
+            store.clear().await.expect("clear");
+
+            // Something to replace, so a replace that keeps old intervals shows up as extra
+            let stale = dataset(50, 1, 5_000);
+            store.update_rune_pool(&stale).await.expect("write stale");
+
+            let replacement = dataset(1_000, 2, 0);
+            store
+                .write_rune_pool(&replacement, WriteSemantics::Replace, Some(strategy))
+                .await
+                .unwrap_or_else(|e| panic!("{} {}: replace failed: {}", backend, strategy, e));
+            assert_stored(store.as_ref(), strategy, "replace", &replacement).await;
+
+            // Overlaps the second half of `replacement` with new values and extends past it
+            let update = dataset(1_000, 3, 500);
+            store
+                .write_rune_pool(&update, WriteSemantics::Upsert, Some(strategy))
+                .await
+                .unwrap_or_else(|e| panic!("{} {}: upsert failed: {}", backend, strategy, e));
+            assert_stored(store.as_ref(), strategy, "upsert", &upserted(&replacement, &update)).await;
+
+            // MongoDB's batch writes reject an empty batch, so no intervals is its own path
+            let empty = dataset(0, 4, 0);
+            let merged = upserted(&replacement, &update);
+            store
+                .write_rune_pool(&empty, WriteSemantics::Upsert, Some(strategy))
+                .await
+                .unwrap_or_else(|e| panic!("{} {}: empty upsert failed: {}", backend, strategy, e));
+            assert_stored(store.as_ref(), strategy, "empty upsert", &upserted(&merged, &empty)).await;
+            store
+                .write_rune_pool(&empty, WriteSemantics::Replace, Some(strategy))
+                .await
+                .unwrap_or_else(|e| panic!("{} {}: empty replace failed: {}", backend, strategy, e));
+            assert_stored(store.as_ref(), strategy, "empty replace", &empty).await;
+        }
+        store.clear().await.expect("clear");
+    }
+}